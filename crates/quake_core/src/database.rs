@@ -0,0 +1,286 @@
+//! Persistent build state, stored in the [`DATABASE_DIR`] directory alongside
+//! the build script.
+//!
+//! For each task call (identified by a key, see [`BuildDatabase::get`]) this
+//! records the most recent run, its outcome and [fingerprint](Fingerprint),
+//! along with the artifacts produced by the task call as of its last successful
+//! run (and, with content hashing, the hashes of its sources and artifacts).
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::metadata::TaskCallMetadata;
+use crate::prelude::*;
+
+/// The name of the directory containing the build database, relative to the
+/// project root.
+pub const DATABASE_DIR: &str = ".quake";
+
+/// The name of the build database file inside of [`DATABASE_DIR`].
+pub const DATABASE_FILE: &str = "build.json";
+
+/// The version of the format of the build database, incremented whenever a
+/// breaking change is made.
+///
+/// Databases with any other version are discarded when opened.
+pub const DATABASE_VERSION: u32 = 1;
+
+/// A hex-encoded SHA-256 hash of the contents of a file.
+pub type ContentHash = String;
+
+/// Build state persisted between invocations of quake.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildDatabase {
+    version: u32,
+    tasks: BTreeMap<String, TaskRecord>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl BuildDatabase {
+    /// Create an empty build database for a project.
+    ///
+    /// The database is not written to disk until [`BuildDatabase::save`] is
+    /// called.
+    pub fn new(project: &Project) -> Self {
+        Self {
+            version: DATABASE_VERSION,
+            tasks: BTreeMap::new(),
+            path: database_path(project),
+        }
+    }
+
+    /// Open the build database for a project, or create an empty one if none
+    /// exists.
+    ///
+    /// Returns why the existing database could not be used if it is unreadable
+    /// or was written in a different format (see [`DATABASE_VERSION`]), in
+    /// which case it should be discarded, e.g. by replacing it with
+    /// [`BuildDatabase::new`].
+    pub fn open(project: &Project) -> Result<Self, String> {
+        let path = database_path(project);
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::new(project)),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        // check the version before anything else, as the format may have changed
+        let version = serde_json::from_str::<DatabaseVersion>(&contents)
+            .map_err(|err| err.to_string())?
+            .version;
+        if version != DATABASE_VERSION {
+            return Err(format!(
+                "discarding build state from a different version of quake (format version \
+                 {version}, expected {DATABASE_VERSION})"
+            ));
+        }
+
+        let database = serde_json::from_str::<Self>(&contents).map_err(|err| err.to_string())?;
+        Ok(Self { path, ..database })
+    }
+
+    /// The path to the database file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write the database to disk, creating the database directory if
+    /// necessary.
+    pub fn save(&self) -> DiagResult<()> {
+        let dir = self.path.parent().unwrap();
+        if !dir.is_dir() {
+            fs::create_dir_all(dir).into_diagnostic()?;
+
+            // keep the build state out of version control
+            fs::write(dir.join(".gitignore"), "*\n").into_diagnostic()?;
+        }
+
+        // write to a temporary file first so that the database is never left
+        // partially written
+        let temp_path = self.path.with_extension("json.tmp");
+        fs::write(&temp_path, serde_json::to_vec(self).into_diagnostic()?).into_diagnostic()?;
+        fs::rename(&temp_path, &self.path).into_diagnostic()?;
+
+        Ok(())
+    }
+
+    /// Get the record for a task call, identified by a key unique to the task
+    /// and its arguments.
+    pub fn get(&self, key: &str) -> Option<&TaskRecord> {
+        self.tasks.get(key)
+    }
+
+    /// Iterate over the records of all task calls, ordered by key.
+    pub fn tasks(&self) -> impl Iterator<Item = (&str, &TaskRecord)> {
+        self.tasks.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Record a run of a task call, along with the artifacts it produced and
+    /// the hashes of its files (only provided when content hashing is enabled).
+    ///
+    /// The artifacts and hashes are only recorded if the run was successful,
+    /// otherwise those from the last successful run are kept.
+    pub fn record_run(
+        &mut self,
+        key: String,
+        run: RunRecord,
+        artifacts: Vec<PathBuf>,
+        files: Option<FileHashes>,
+    ) {
+        let succeeded = run.outcome == RunOutcome::Succeeded;
+        match self.tasks.get_mut(&key) {
+            Some(record) => {
+                if succeeded {
+                    record.artifacts = artifacts;
+                    record.files = files;
+                }
+                record.last_run = run;
+            }
+            None => {
+                self.tasks.insert(
+                    key,
+                    TaskRecord {
+                        last_run: run,
+                        artifacts: if succeeded { artifacts } else { Vec::new() },
+                        files: files.filter(|_| succeeded),
+                    },
+                );
+            }
+        }
+    }
+}
+
+fn database_path(project: &Project) -> PathBuf {
+    project
+        .project_root()
+        .join(DATABASE_DIR)
+        .join(DATABASE_FILE)
+}
+
+#[derive(Deserialize)]
+struct DatabaseVersion {
+    version: u32,
+}
+
+/// The persisted state of a single task call.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskRecord {
+    /// The most recent run of the task call.
+    pub last_run: RunRecord,
+    /// The artifacts produced by the task call as of its last successful run,
+    /// relative to the project root.
+    pub artifacts: Vec<PathBuf>,
+    /// The state of the files of the task call as of its last successful run.
+    pub files: Option<FileHashes>,
+}
+
+/// A single run of a task call.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub started_at: SystemTime,
+    pub duration_ms: u64,
+    pub outcome: RunOutcome,
+    /// The fingerprint of the task call at the time of the run.
+    pub fingerprint: ContentHash,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunOutcome {
+    Succeeded,
+    Failed,
+    Interrupted,
+}
+
+/// Hashes of the sources and artifacts of a task call.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileHashes {
+    /// Hashes of the sources of the task call, excluding any that did not
+    /// exist.
+    pub sources: BTreeMap<PathBuf, ContentHash>,
+    /// Hashes of the artifacts of the task call, excluding any that did not
+    /// exist.
+    pub artifacts: BTreeMap<PathBuf, ContentHash>,
+}
+
+impl FileHashes {
+    /// Hash the current sources and artifacts of a task call, relative to the
+    /// project root.
+    pub fn from_metadata(project_root: &Path, metadata: &TaskCallMetadata) -> DiagResult<Self> {
+        Ok(Self {
+            sources: hash_files(project_root, &metadata.sources)?,
+            artifacts: hash_files(project_root, &metadata.artifacts)?,
+        })
+    }
+}
+
+/// Hashes the inputs of a task call other than its files, such as its
+/// arguments and the source text of the task, so that changes to them can be
+/// detected.
+///
+/// Each part is length-prefixed, so that the boundaries between parts affect
+/// the resulting hash.
+#[derive(Debug, Clone, Default)]
+pub struct Fingerprint(Sha256);
+
+impl Fingerprint {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add a part to the fingerprint.
+    pub fn add(&mut self, part: impl AsRef<[u8]>) -> &mut Self {
+        let part = part.as_ref();
+        self.0.update((part.len() as u64).to_le_bytes());
+        self.0.update(part);
+        self
+    }
+
+    pub fn finish(self) -> ContentHash {
+        to_hex(&self.0.finalize())
+    }
+}
+
+/// Hash the contents of a file, returning `None` if it does not exist.
+pub fn hash_file(path: impl AsRef<Path>) -> io::Result<Option<ContentHash>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+
+    Ok(Some(to_hex(&hasher.finalize())))
+}
+
+/// Hash the contents of multiple files relative to a directory, skipping any
+/// that do not exist.
+pub fn hash_files(
+    dir: &Path,
+    paths: &[impl AsRef<Path>],
+) -> DiagResult<BTreeMap<PathBuf, ContentHash>> {
+    let mut hashes = BTreeMap::new();
+
+    for path in paths {
+        let path = path.as_ref();
+        if let Some(hash) = hash_file(dir.join(path)).into_diagnostic()? {
+            hashes.insert(path.to_owned(), hash);
+        }
+    }
+
+    Ok(hashes)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -2,6 +2,8 @@ pub use {quake_errors as errors, quake_log as log};
 
 mod macros;
 
+#[cfg(feature = "serde")]
+pub mod database;
 pub mod metadata;
 pub mod project;
 pub mod utils;
@@ -10,6 +12,10 @@ pub mod utils;
 /// descending precedence.
 pub const BUILD_SCRIPT_NAMES: &[&str] = &["build.quake", "build.quake.nu"];
 
+/// The environment variable used to set the maximum number of tasks to run at
+/// once, which is also set for tasks to the limit currently in effect.
+pub const JOBS_ENV: &str = "QUAKE_JOBS";
+
 pub mod prelude {
     pub use quake_errors::*;
     pub use quake_log::{log_error, log_fatal, log_info, log_warning, panic_bug};
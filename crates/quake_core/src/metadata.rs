@@ -1,8 +1,8 @@
 use std::ops::{Deref, DerefMut};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
-use nu_protocol::ast::Argument;
 use nu_protocol::{BlockId, DeclId, Span, Spanned, Value, VarId};
 use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
@@ -39,26 +39,25 @@ impl Metadata {
         self.tasks
             .iter()
             .find(|t| t.name.item == name)
-            .ok_or_else(|| {
-                errors::TaskNotFound {
-                    name: name.to_owned(),
-                    span,
-                }
-                .into()
-            })
+            .ok_or_else(|| self.task_not_found(name, span).into())
     }
 
     pub fn find_task_id(&self, name: &str, span: Option<Span>) -> DiagResult<TaskId> {
         self.tasks
             .iter()
             .position(|t| t.name.item == name)
-            .ok_or_else(|| {
-                errors::TaskNotFound {
-                    name: name.to_owned(),
-                    span,
-                }
-                .into()
-            })
+            .ok_or_else(|| self.task_not_found(name, span).into())
+    }
+
+    /// Create an error for a task named `name` that could not be found,
+    /// suggesting similarly named tasks that can be called.
+    pub fn task_not_found(&self, name: &str, span: Option<Span>) -> errors::TaskNotFound {
+        let tasks = self
+            .tasks
+            .iter()
+            .filter(|t| !t.is_subtask())
+            .map(|t| t.name.item.as_str());
+        errors::TaskNotFound::new(name, span, tasks)
     }
 
     pub fn register_task(
@@ -96,14 +95,15 @@ impl Metadata {
     ///
     /// This will always result in a new task call ID, even if an otherwise
     /// identical one already exists, so that individual invocations are
-    /// tracked.
+    /// tracked. Identical task calls are instead merged by the engine once
+    /// their arguments have been evaluated.
     ///
     /// Returns `None` when there is no task for `task_id`.
     pub fn register_task_call(
         &mut self,
         task_id: TaskId,
         span: Span,
-        arguments: Vec<Argument>,
+        arguments: Vec<TaskArgument>,
         constants: Vec<(VarId, Value)>,
     ) -> Option<TaskCallId> {
         let _task = self.get_task(task_id)?;
@@ -145,6 +145,11 @@ impl Metadata {
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Task {
     pub name: Spanned<String>,
+    /// The doc comment above the definition of the task, if any.
+    pub description: Option<String>,
+    /// The names of the tasks depended upon with `depends` in the declaration
+    /// body of the task (regardless of the arguments passed to them).
+    pub depends: Vec<String>,
     pub flags: TaskFlags,
     #[cfg_attr(feature = "serde", serde(skip))]
     pub depends_decl_id: Option<DeclId>,
@@ -154,10 +159,30 @@ pub struct Task {
     pub run_body: Option<BlockId>,
 }
 
+impl Task {
+    /// Whether the task is an anonymous subtask defined inside of another task
+    /// (named `parent/child`), which cannot be depended upon by name.
+    pub fn is_subtask(&self) -> bool {
+        self.name.item.contains('/') && self.depends_decl_id.is_none()
+    }
+}
+
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TaskFlags {
     pub concurrent: bool,
+    /// Whether the task only has a declaration body, and no run body.
+    pub pure: bool,
+    /// Whether to run the task once for every call, rather than merging calls
+    /// with identical arguments.
+    pub no_dedup: bool,
+    /// The maximum amount of time the task may run for before it is stopped.
+    pub timeout: Option<Duration>,
+    /// The number of times to re-run the task if it fails.
+    pub retries: u32,
+    /// How long to wait before re-running the task after its first failure,
+    /// doubling after each subsequent failure.
+    pub retry_delay: Option<Duration>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -165,11 +190,23 @@ pub struct TaskFlags {
 pub struct TaskCall {
     pub task_id: TaskId,
     pub span: Span,
-    pub arguments: Vec<Argument>,
+    pub arguments: Vec<TaskArgument>,
     pub constants: Vec<(VarId, Value)>,
     pub metadata: TaskCallMetadata, // TODO box this as well
 }
 
+/// An argument passed to a task call, evaluated when the task call was made
+/// (as any variables it refers to may no longer be in scope once it is run).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TaskArgument {
+    /// A positional argument, including any spread into the rest parameter.
+    Positional(Value),
+    /// A flag, given by its long name, along with its value (unless it is a
+    /// switch).
+    Named(String, Option<Value>),
+}
+
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TaskCallMetadata {
@@ -1,9 +1,14 @@
-use std::fs;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
+use std::{fmt, fs};
 
 use nu_protocol::engine::PWD_ENV;
 
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+#[cfg(feature = "serde")]
+use crate::database::{hash_files, RunOutcome, TaskRecord};
 use crate::metadata::TaskCallMetadata;
 use crate::prelude::*;
 
@@ -13,21 +18,243 @@ pub fn get_init_cwd() -> Option<PathBuf> {
         .or_else(|| std::env::var(PWD_ENV).ok().map(Into::into))
 }
 
-pub fn latest_timestamp(paths: &[impl AsRef<Path>]) -> DiagResult<Option<SystemTime>> {
-    Ok(paths
-        .iter()
-        .filter(|p| p.as_ref().exists())
-        .map(|s| fs::metadata(s).and_then(|m| m.modified()).into_diagnostic())
-        .collect::<DiagResult<Vec<_>>>()?
-        .into_iter()
-        .max())
+/// Find the most recently modified of the given paths that exist, along with
+/// its modification time.
+pub fn latest_timestamp<P: AsRef<Path>>(
+    paths: &[P],
+    root: &Path,
+) -> DiagResult<Option<(&P, SystemTime)>> {
+    let mut latest = None;
+    for path in paths {
+        let full_path = root.join(path);
+        if !full_path.exists() {
+            continue;
+        }
+
+        let modified = fs::metadata(full_path)
+            .and_then(|m| m.modified())
+            .into_diagnostic()?;
+        if latest.is_none_or(|(_, latest)| modified > latest) {
+            latest = Some((path, modified));
+        }
+    }
+
+    Ok(latest)
+}
+
+/// A file along with the time it was last modified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct FileTimestamp {
+    pub path: PathBuf,
+    pub modified: SystemTime,
+}
+
+impl FileTimestamp {
+    fn new(path: &Path, modified: SystemTime) -> Self {
+        Self {
+            path: path.to_owned(),
+            modified,
+        }
+    }
+}
+
+/// Whether a task call is dirty, and why, as determined by [`check_dirty`] or
+/// [`check_dirty_hashed`].
+///
+/// Paths are relative to the project root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize),
+    serde(tag = "reason", rename_all = "snake_case")
+)]
+pub enum Dirtiness {
+    /// The most recently modified source (if any exist) is no newer than the
+    /// most recently modified artifact (if any exist).
+    UpToDate {
+        source: Option<FileTimestamp>,
+        artifact: Option<FileTimestamp>,
+    },
+    /// None of the sources or artifacts of the task call have changed since
+    /// its last successful run.
+    Unchanged,
+    /// The task call was forced to run (e.g. with `--force`).
+    Forced,
+    /// The task call declares no sources, so it is always run.
+    NoSources,
+    /// The task call declares no artifacts, so it is always run.
+    NoArtifacts,
+    /// None of the artifacts of the task call exist, while some of its sources
+    /// do.
+    ArtifactsMissing,
+    /// The most recently modified source is newer than every artifact.
+    SourceNewer {
+        source: FileTimestamp,
+        artifact: FileTimestamp,
+    },
+    /// The task call has never been run before.
+    NeverRun,
+    /// The [fingerprint](crate::database::Fingerprint) of the task call (e.g.
+    /// its arguments) has changed since its last run.
+    FingerprintChanged,
+    /// The last run of the task call was unsuccessful.
+    LastRunFailed,
+    /// The sources and artifacts of the task call were not hashed after its
+    /// last successful run, as content hashing was not enabled at the time.
+    NotHashed,
+    /// A source has been created, modified or deleted since the last
+    /// successful run.
+    SourceChanged { source: PathBuf },
+    /// An artifact has been modified since the last successful run.
+    ArtifactChanged { artifact: PathBuf },
+    /// An artifact has been deleted since the last successful run.
+    ArtifactMissing { artifact: PathBuf },
+}
+
+impl Dirtiness {
+    pub fn is_dirty(&self) -> bool {
+        !matches!(self, Dirtiness::UpToDate { .. } | Dirtiness::Unchanged)
+    }
+}
+
+impl fmt::Display for Dirtiness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dirtiness::UpToDate { .. } | Dirtiness::Unchanged => write!(f, "up to date"),
+            Dirtiness::Forced => write!(f, "forced to run"),
+            Dirtiness::NoSources => write!(f, "no sources are declared"),
+            Dirtiness::NoArtifacts => write!(f, "no artifacts are declared"),
+            Dirtiness::ArtifactsMissing => write!(f, "none of its artifacts exist"),
+            Dirtiness::SourceNewer { source, artifact } => write!(
+                f,
+                "source `{}` is newer than artifact `{}`",
+                source.path.display(),
+                artifact.path.display()
+            ),
+            Dirtiness::NeverRun => write!(f, "it has never been run"),
+            Dirtiness::FingerprintChanged => {
+                write!(
+                    f,
+                    "its arguments or definition have changed since its last run"
+                )
+            }
+            Dirtiness::LastRunFailed => write!(f, "its last run was unsuccessful"),
+            Dirtiness::NotHashed => write!(
+                f,
+                "its files were not hashed after its last run, as content hashing was not enabled"
+            ),
+            Dirtiness::SourceChanged { source } => {
+                write!(f, "source `{}` has changed", source.display())
+            }
+            Dirtiness::ArtifactChanged { artifact } => {
+                write!(f, "artifact `{}` has changed", artifact.display())
+            }
+            Dirtiness::ArtifactMissing { artifact } => {
+                write!(f, "artifact `{}` has been deleted", artifact.display())
+            }
+        }
+    }
+}
+
+/// Check whether a task call is dirty by comparing the modification times of
+/// its sources and artifacts, which are relative to the project root.
+pub fn check_dirty(task: &TaskCallMetadata, project_root: &Path) -> DiagResult<Dirtiness> {
+    // if either is undefined, assume dirty
+    if task.sources.is_empty() {
+        return Ok(Dirtiness::NoSources);
+    }
+    if task.artifacts.is_empty() {
+        return Ok(Dirtiness::NoArtifacts);
+    }
+
+    let source = latest_timestamp(&task.sources, project_root)?;
+    let artifact = latest_timestamp(&task.artifacts, project_root)?;
+
+    let source = source.map(|(path, modified)| FileTimestamp::new(path, modified));
+    let artifact = artifact.map(|(path, modified)| FileTimestamp::new(path, modified));
+
+    Ok(match (source, artifact) {
+        (Some(_), None) => Dirtiness::ArtifactsMissing,
+        (Some(source), Some(artifact)) if source.modified > artifact.modified => {
+            Dirtiness::SourceNewer { source, artifact }
+        }
+        (source, artifact) => Dirtiness::UpToDate { source, artifact },
+    })
+}
+
+/// Check whether the [fingerprint](crate::database::Fingerprint) of a task call
+/// differs from that of its last recorded run, or if it has never been run,
+/// returning why the task call is dirty if so.
+#[cfg(feature = "serde")]
+pub fn check_fingerprint(fingerprint: &str, record: Option<&TaskRecord>) -> Option<Dirtiness> {
+    match record {
+        None => Some(Dirtiness::NeverRun),
+        Some(record) if record.last_run.fingerprint != fingerprint => {
+            Some(Dirtiness::FingerprintChanged)
+        }
+        Some(_) => None,
+    }
 }
 
-pub fn is_dirty(task: &TaskCallMetadata) -> DiagResult<bool> {
+/// Check whether a task call is dirty by comparing the hashes of its sources
+/// and artifacts to those recorded after its last successful run.
+///
+/// A task call is dirty if its last run was unsuccessful, if its fingerprint
+/// has changed, if any of its sources have changed, or if any of its artifacts
+/// have been deleted or modified since.
+#[cfg(feature = "serde")]
+pub fn check_dirty_hashed(
+    task: &TaskCallMetadata,
+    project_root: &Path,
+    fingerprint: &str,
+    record: Option<&TaskRecord>,
+) -> DiagResult<Dirtiness> {
     // if either is undefined, assume dirty
-    if task.sources.is_empty() || task.artifacts.is_empty() {
-        return Ok(true);
+    if task.sources.is_empty() {
+        return Ok(Dirtiness::NoSources);
+    }
+    if task.artifacts.is_empty() {
+        return Ok(Dirtiness::NoArtifacts);
+    }
+
+    if let Some(dirtiness) = check_fingerprint(fingerprint, record) {
+        return Ok(dirtiness);
+    }
+
+    // never run successfully
+    let Some(record) = record.filter(|r| r.last_run.outcome == RunOutcome::Succeeded) else {
+        return Ok(Dirtiness::LastRunFailed);
+    };
+    // files are only hashed after successful runs with content hashing enabled
+    let Some(record) = &record.files else {
+        return Ok(Dirtiness::NotHashed);
+    };
+
+    // sources may have been created or deleted, as well as modified
+    let sources = hash_files(project_root, &task.sources)?;
+    let changed_source = (sources.keys().chain(record.sources.keys()))
+        .find(|s| sources.get(*s) != record.sources.get(*s));
+    if let Some(source) = changed_source {
+        return Ok(Dirtiness::SourceChanged {
+            source: source.clone(),
+        });
+    }
+
+    let artifacts = hash_files(project_root, &task.artifacts)?;
+    for artifact in &task.artifacts {
+        let current = artifacts.get(artifact.as_path());
+        if current.is_none() {
+            return Ok(Dirtiness::ArtifactMissing {
+                artifact: artifact.clone(),
+            });
+        }
+        if current != record.artifacts.get(artifact.as_path()) {
+            return Ok(Dirtiness::ArtifactChanged {
+                artifact: artifact.clone(),
+            });
+        }
     }
 
-    Ok(latest_timestamp(&task.sources)? > latest_timestamp(&task.artifacts)?)
+    Ok(Dirtiness::Unchanged)
 }
@@ -0,0 +1,250 @@
+//! Events emitted by the engine as line-delimited JSON objects (see
+//! [`EngineOptions::json`](crate::EngineOptions::json)).
+//!
+//! Each line written to stderr is a single JSON object with a `version` field
+//! (see [`EVENT_SCHEMA_VERSION`]) and an `event` field naming the kind of the
+//! event, alongside the fields of the corresponding [`Event`] variant.
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use nu_protocol::engine::{EngineState, StateWorkingSet};
+use nu_protocol::{report_error, report_error_new};
+use serde::Serialize;
+
+use quake_core::errors::miette::{Diagnostic, Severity, SourceCode};
+use quake_core::metadata::TaskCallId;
+use quake_core::prelude::*;
+
+/// The version of the event schema, incremented whenever a breaking change is
+/// made to the structure of any event.
+pub const EVENT_SCHEMA_VERSION: u32 = 1;
+
+/// An event emitted by the engine during a build.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A task call was added to the run graph, and will be run (or skipped)
+    /// later in the build.
+    TaskScheduled { call_id: TaskCallId, task: String },
+    /// A task call has started running.
+    TaskStarted { call_id: TaskCallId, task: String },
+    /// A task call has failed, and will be re-run after a delay.
+    TaskRetrying {
+        call_id: TaskCallId,
+        task: String,
+        /// The number of the upcoming attempt, starting from 1 for the initial
+        /// run.
+        attempt: u32,
+        delay_ms: u64,
+    },
+    /// The captured output of a task call that has failed (see
+    /// [`EngineOptions::quiet`](crate::EngineOptions::quiet)).
+    TaskOutput {
+        call_id: TaskCallId,
+        task: String,
+        output: String,
+    },
+    /// A task call was skipped as it was not dirty.
+    TaskSkipped { call_id: TaskCallId, task: String },
+    /// A task call has finished running.
+    TaskFinished {
+        call_id: TaskCallId,
+        task: String,
+        success: bool,
+        duration_ms: u64,
+    },
+    /// The build was interrupted by the user, and the running task calls have
+    /// been asked to stop.
+    BuildInterrupted { running: Vec<TaskCallId> },
+    /// A problem was encountered that does not affect the outcome of the
+    /// build.
+    Warning { title: String, message: String },
+    /// A diagnostic was reported, either by quake or by nushell.
+    Diagnostic(DiagnosticEvent),
+    /// The build has finished, successfully or otherwise.
+    BuildSummary {
+        success: bool,
+        duration_ms: u64,
+        succeeded: usize,
+        skipped: usize,
+        failed: usize,
+        /// Task calls stopped while running, as the build had failed.
+        cancelled: usize,
+        /// Task calls that never started, as the build had failed.
+        not_started: usize,
+    },
+}
+
+/// A diagnostic reported during a build, flattened from a
+/// [`miette::Diagnostic`].
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticEvent {
+    /// The task call being run when the diagnostic was reported, if any.
+    pub call_id: Option<TaskCallId>,
+    pub severity: &'static str,
+    pub code: Option<String>,
+    pub message: String,
+    pub help: Option<String>,
+    pub labels: Vec<DiagnosticLabel>,
+}
+
+/// A labelled location inside of a source file.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticLabel {
+    pub label: Option<String>,
+    pub file: Option<String>,
+    /// Line number (1-indexed).
+    pub line: usize,
+    /// Column number (1-indexed).
+    pub column: usize,
+    /// Length of the span in bytes.
+    pub length: usize,
+}
+
+impl DiagnosticEvent {
+    fn new(
+        call_id: Option<TaskCallId>,
+        working_set: &StateWorkingSet<'_>,
+        diagnostic: &dyn Diagnostic,
+    ) -> Self {
+        let source: &dyn SourceCode = &working_set;
+
+        let labels = diagnostic
+            .labels()
+            .into_iter()
+            .flatten()
+            .map(|label| {
+                let (file, line, column) = match source.read_span(label.inner(), 0, 0) {
+                    Ok(contents) => (
+                        contents.name().map(ToOwned::to_owned),
+                        contents.line() + 1,
+                        label.offset() - contents.span().offset() + contents.column() + 1,
+                    ),
+                    Err(_) => (None, 0, 0),
+                };
+
+                DiagnosticLabel {
+                    label: label.label().map(ToOwned::to_owned),
+                    file,
+                    line,
+                    column,
+                    length: label.len(),
+                }
+            })
+            .collect();
+
+        Self {
+            call_id,
+            severity: match diagnostic.severity().unwrap_or(Severity::Error) {
+                Severity::Advice => "advice",
+                Severity::Warning => "warning",
+                Severity::Error => "error",
+            },
+            code: diagnostic.code().map(|c| c.to_string()),
+            message: diagnostic.to_string(),
+            help: diagnostic.help().map(|h| h.to_string()),
+            labels,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct EventRecord<'a> {
+    version: u32,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Reports events and diagnostics, either as line-delimited JSON objects or in
+/// a human-readable format, depending on whether JSON output was requested.
+#[derive(Debug, Clone, Copy)]
+pub struct EventEmitter {
+    json: bool,
+}
+
+impl EventEmitter {
+    pub const fn new(json: bool) -> Self {
+        Self { json }
+    }
+
+    pub const fn is_json(&self) -> bool {
+        self.json
+    }
+
+    /// Emit an event as a JSON object to stderr, if JSON output is enabled.
+    pub fn emit(&self, event: Event) {
+        if !self.json {
+            return;
+        }
+
+        let record = EventRecord {
+            version: EVENT_SCHEMA_VERSION,
+            event: &event,
+        };
+
+        let mut line = serde_json::to_string(&record)
+            .unwrap_or_else(|err| panic_bug!("failed to serialize event: {err}"));
+        line.push('\n');
+
+        // write the entire line at once so that concurrent tasks don't interleave
+        let _ = io::stderr().lock().write_all(line.as_bytes());
+    }
+
+    /// Report a diagnostic in the context of a working set, like
+    /// [`report_error`].
+    pub fn report_error(
+        &self,
+        call_id: Option<TaskCallId>,
+        working_set: &StateWorkingSet<'_>,
+        error: &(dyn Diagnostic + Send + Sync + 'static),
+    ) {
+        if self.json {
+            self.emit(Event::Diagnostic(DiagnosticEvent::new(
+                call_id,
+                working_set,
+                error,
+            )));
+        } else {
+            report_error(working_set, error);
+        }
+    }
+
+    /// Report a diagnostic in the context of an engine state, like
+    /// [`report_error_new`].
+    pub fn report_error_new(
+        &self,
+        call_id: Option<TaskCallId>,
+        engine_state: &EngineState,
+        error: &(dyn Diagnostic + Send + Sync + 'static),
+    ) {
+        if self.json {
+            self.report_error(call_id, &StateWorkingSet::new(engine_state), error);
+        } else {
+            report_error_new(engine_state, error);
+        }
+    }
+
+    /// Log a human-readable message, if JSON output is not enabled.
+    pub fn log_info(&self, title: &str, message: &str) {
+        if !self.json {
+            log_info!(title, message);
+        }
+    }
+
+    /// Report a warning, either as an event or as a human-readable message.
+    pub fn log_warning(&self, title: &str, message: &str) {
+        if self.json {
+            self.emit(Event::Warning {
+                title: title.to_owned(),
+                message: message.to_owned(),
+            });
+        } else {
+            log_warning!(title, message);
+        }
+    }
+}
+
+pub(crate) fn duration_ms(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
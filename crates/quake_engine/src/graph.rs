@@ -0,0 +1,100 @@
+//! The dependency graph of task calls, as exported by `quake graph` (see
+//! [`Engine::graph`](crate::Engine::graph)).
+
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use quake_core::metadata::TaskCallId;
+
+/// A graph of task calls, with an edge from each task call to each of the task
+/// calls it depends on.
+#[derive(Debug, Clone, Serialize)]
+pub struct CallGraph {
+    /// The task calls in the graph, in an order of execution.
+    pub calls: Vec<CallNode>,
+    pub edges: Vec<CallEdge>,
+}
+
+/// A task call in a [`CallGraph`].
+#[derive(Debug, Clone, Serialize)]
+pub struct CallNode {
+    pub call_id: TaskCallId,
+    pub task: String,
+    /// The task along with its arguments, e.g. `build --release`.
+    pub call: String,
+    /// Whether the task call was requested directly, rather than only as a
+    /// dependency of another task call.
+    pub root: bool,
+    /// Whether the task call is of a subtask defined with `subtask`.
+    pub subtask: bool,
+}
+
+/// A dependency of one task call upon another in a [`CallGraph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CallEdge {
+    /// The task call with the dependency.
+    pub from: TaskCallId,
+    /// The task call depended upon.
+    pub to: TaskCallId,
+}
+
+impl CallGraph {
+    /// Render the graph in the DOT language used by Graphviz.
+    ///
+    /// Task calls that were requested directly are drawn in bold, and those of
+    /// subtasks are drawn dashed.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph quake {\n");
+
+        for node in &self.calls {
+            let mut attrs = format!("label=\"{}\"", escape_dot(&node.call));
+            if node.root {
+                attrs.push_str(", style=bold");
+            } else if node.subtask {
+                attrs.push_str(", style=dashed");
+            }
+            writeln!(dot, "    call{} [{attrs}];", node.call_id).unwrap();
+        }
+
+        for edge in &self.edges {
+            writeln!(dot, "    call{} -> call{};", edge.from, edge.to).unwrap();
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Render the graph as a Mermaid flowchart.
+    ///
+    /// Task calls of subtasks are drawn with rounded edges.
+    pub fn to_mermaid(&self) -> String {
+        let mut mermaid = String::from("flowchart TD\n");
+
+        for node in &self.calls {
+            let label = escape_mermaid(&node.call);
+            if node.subtask {
+                writeln!(mermaid, "    call{}(\"{label}\")", node.call_id).unwrap();
+            } else {
+                writeln!(mermaid, "    call{}[\"{label}\"]", node.call_id).unwrap();
+            }
+        }
+
+        for edge in &self.edges {
+            writeln!(mermaid, "    call{} --> call{}", edge.from, edge.to).unwrap();
+        }
+
+        mermaid
+    }
+}
+
+fn escape_dot(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn escape_mermaid(label: &str) -> String {
+    label.replace('"', "#quot;")
+}
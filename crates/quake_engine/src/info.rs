@@ -0,0 +1,148 @@
+//! Descriptions of tasks and their parameters, as shown to the user (see
+//! [`Engine::describe_tasks`](crate::Engine::describe_tasks)).
+
+use nu_protocol::{Config, Flag, PositionalArg, Signature, Value};
+use serde::Serialize;
+
+use quake_core::metadata::Task;
+
+/// A description of a task, taken from its definition in the build script.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskInfo {
+    pub name: String,
+    /// The doc comment above the task's definition, if any.
+    pub description: Option<String>,
+    pub concurrent: bool,
+    pub pure: bool,
+    /// Whether this is an anonymous subtask (e.g. `parent/child`) defined with
+    /// `subtask`, which cannot be called directly.
+    pub subtask: bool,
+    pub params: Vec<ParamInfo>,
+    /// See [`Task::depends`].
+    pub depends: Vec<String>,
+}
+
+impl TaskInfo {
+    pub(crate) fn new(task: &Task, signature: Option<&Signature>, config: &Config) -> Self {
+        let mut params = Vec::new();
+        if let Some(signature) = signature {
+            let positional = |kind, arg: &PositionalArg| ParamInfo::positional(kind, arg, config);
+            for arg in &signature.required_positional {
+                params.push(positional(ParamKind::Required, arg));
+            }
+            for arg in &signature.optional_positional {
+                params.push(positional(ParamKind::Optional, arg));
+            }
+            if let Some(arg) = &signature.rest_positional {
+                params.push(positional(ParamKind::Rest, arg));
+            }
+            for flag in signature.named.iter().filter(|flag| flag.long != "help") {
+                params.push(ParamInfo::flag(flag, config));
+            }
+        }
+
+        Self {
+            name: task.name.item.clone(),
+            description: task.description.clone(),
+            concurrent: task.flags.concurrent,
+            pure: task.flags.pure,
+            subtask: task.is_subtask(),
+            params,
+            depends: task.depends.clone(),
+        }
+    }
+
+    /// The first line of the description, if any.
+    pub fn summary(&self) -> Option<&str> {
+        self.description.as_deref().and_then(|d| d.lines().next())
+    }
+
+    /// Whether the task can only be called with arguments, as some of its
+    /// parameters are required.
+    pub fn requires_arguments(&self) -> bool {
+        self.params
+            .iter()
+            .any(|p| matches!(p.kind, ParamKind::Required | ParamKind::RequiredFlag))
+    }
+
+    /// A one-line usage of the task, e.g. `build <target> [--release]`.
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.clone();
+        for param in &self.params {
+            usage.push(' ');
+            usage.push_str(&param.usage());
+        }
+        usage
+    }
+}
+
+/// A description of a single parameter of a task.
+#[derive(Debug, Clone, Serialize)]
+pub struct ParamInfo {
+    pub name: String,
+    pub kind: ParamKind,
+    /// The short name of a flag, if any.
+    pub short: Option<char>,
+    /// The shape of the value taken by the parameter, or `None` for switches.
+    pub shape: Option<String>,
+    pub description: String,
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamKind {
+    Required,
+    Optional,
+    Rest,
+    Flag,
+    RequiredFlag,
+}
+
+impl ParamInfo {
+    fn positional(kind: ParamKind, arg: &PositionalArg, config: &Config) -> Self {
+        Self {
+            name: arg.name.clone(),
+            kind,
+            short: None,
+            shape: Some(arg.shape.to_string()),
+            description: arg.desc.clone(),
+            default: default_value(arg.default_value.as_ref(), config),
+        }
+    }
+
+    fn flag(flag: &Flag, config: &Config) -> Self {
+        Self {
+            name: flag.long.clone(),
+            kind: if flag.required {
+                ParamKind::RequiredFlag
+            } else {
+                ParamKind::Flag
+            },
+            short: flag.short,
+            shape: flag.arg.as_ref().map(ToString::to_string),
+            description: flag.desc.clone(),
+            default: default_value(flag.default_value.as_ref(), config),
+        }
+    }
+
+    /// The usage of the parameter, e.g. `<target>`, `[--jobs <int>]`.
+    pub fn usage(&self) -> String {
+        let flag = || match &self.shape {
+            Some(shape) => format!("--{} <{shape}>", self.name),
+            None => format!("--{}", self.name),
+        };
+
+        match self.kind {
+            ParamKind::Required => format!("<{}>", self.name),
+            ParamKind::Optional => format!("[{}]", self.name),
+            ParamKind::Rest => format!("...{}", self.name),
+            ParamKind::Flag => format!("[{}]", flag()),
+            ParamKind::RequiredFlag => flag(),
+        }
+    }
+}
+
+fn default_value(value: Option<&Value>, config: &Config) -> Option<String> {
+    value.map(|value| value.to_expanded_string(", ", config))
+}
@@ -1,49 +1,333 @@
 #![feature(let_chains)]
 #![allow(dead_code)]
 
-use std::collections::HashMap;
-use std::fs;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::thread::available_parallelism;
+use std::time::{Duration, Instant, SystemTime};
 
-use nu_parser::parse;
+use nu_parser::{lex, parse, parse_internal_call};
 use nu_protocol::ast::{Argument, Block};
 use nu_protocol::engine::{EngineState, Stack, StateWorkingSet};
-use nu_protocol::{report_error, report_error_new, Span};
+use nu_protocol::{Span, Value};
 use parking_lot::{Mutex, RwLock, RwLockReadGuard};
-use tokio::runtime::Runtime;
-use tokio::task::{AbortHandle, JoinSet};
-
-use quake_core::metadata::{Metadata, TaskCallId};
+use tempfile::tempfile;
+use tokio::runtime::Builder;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task::{spawn_blocking, AbortHandle, JoinSet};
+use tokio::time::{sleep, sleep_until};
+
+use quake_core::database::{BuildDatabase, ContentHash, FileHashes, RunOutcome, RunRecord};
+use quake_core::errors::miette::LabeledSpan;
+use quake_core::metadata::{Metadata, TaskCallId, TaskCallMetadata, TaskId};
 use quake_core::prelude::*;
-use quake_core::utils::is_dirty;
-
-use crate::nu::eval::{eval_block, eval_task_decl_body, eval_task_run_body};
+use quake_core::utils::{check_dirty, check_dirty_hashed, check_fingerprint, Dirtiness};
+use quake_core::JOBS_ENV;
+
+use crate::events::{duration_ms, Event, EventEmitter};
+use crate::graph::{CallEdge, CallGraph, CallNode};
+use crate::info::TaskInfo;
+use crate::nu::eval::{
+    describe_task_call, eval_arguments, eval_block, eval_task_decl_body, eval_task_run_body,
+    eval_task_run_body_redirected, fingerprint_task_call,
+};
 use crate::nu::parse::parse_metadata;
 use crate::nu::{create_engine_state, create_stack};
-use crate::run_tree::{generate_run_tree, RunNode};
+use crate::plan::{Plan, PlannedCall};
+use crate::run_graph::RunGraph;
 use crate::state::State;
+use crate::watch::Watcher;
+
+pub mod events;
+pub mod graph;
+pub mod info;
+pub mod plan;
 
 mod nu;
-mod run_tree;
+mod run_graph;
 mod state;
+mod watch;
+
+/// The name of the virtual file containing the task call provided from the
+/// command line, used when reporting diagnostics.
+const COMMAND_LINE_FILENAME: &str = "<commandline>";
 
 #[derive(Debug, Clone)]
 pub struct EngineOptions {
     pub quiet: bool,
     pub json: bool,
-    pub force: bool,
+    pub force: ForceMode,
     pub watch: bool,
+    /// Whether to determine dirtiness from the hashes of the contents of
+    /// sources and artifacts (stored in the [`BuildDatabase`]), rather than
+    /// their modification times.
+    pub content_hash: bool,
+    /// The maximum number of tasks to run at once, overriding the default set
+    /// by the build script (see [`Engine::jobs`]).
+    pub jobs: Option<NonZeroUsize>,
+    /// Whether to keep running tasks that do not depend on a failed task,
+    /// rather than stopping the build at the first failure.
+    pub keep_going: bool,
+    /// The maximum amount of time any task may run for, overriding the
+    /// timeouts set by the build script (see [`Engine::timeout`]).
+    pub timeout: Option<Duration>,
+}
+
+/// The maximum depth of dependencies below a task call, past which a task
+/// depending on itself (with different arguments each time) is reported as a
+/// dependency cycle rather than being followed until the stack overflows.
+const MAX_DEPENDENCY_DEPTH: usize = 512;
+
+/// How long to wait for aborted task calls to stop once a build has finished,
+/// before exiting regardless.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often to check whether a task call has been interrupted while waiting
+/// to retry it.
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A task call identified by its task and [fingerprint](fingerprint_task_call),
+/// as every `depends` registers a new task call.
+type CallIdentity = (TaskId, ContentHash);
+
+/// Task calls visited while populating metadata (see
+/// [`Engine::populate_metadata_for_call_id`]).
+#[derive(Debug, Default)]
+struct VisitedCalls {
+    /// The task calls leading to the current one, used to detect cycles.
+    path: Vec<(TaskCallId, CallIdentity)>,
+    /// The first task call populated with each identity, which identical task
+    /// calls are merged into.
+    populated: HashMap<CallIdentity, TaskCallId>,
+}
+
+impl VisitedCalls {
+    /// Find the cycle formed by visiting a task call with the given identity
+    /// next, given as the task calls making up the cycle (where the first and
+    /// last are calls to the same task).
+    ///
+    /// Once the path reaches [`MAX_DEPENDENCY_DEPTH`], the most recent
+    /// call to the same task is treated as the start of a cycle.
+    fn find_cycle(&self, call_id: TaskCallId, identity: &CallIdentity) -> Option<Vec<TaskCallId>> {
+        let start = self
+            .path
+            .iter()
+            .position(|(_, i)| i == identity)
+            .or_else(|| {
+                (self.path.len() >= MAX_DEPENDENCY_DEPTH).then(|| {
+                    (self.path.iter())
+                        .rposition(|(_, (t, _))| *t == identity.0)
+                        .unwrap_or(0)
+                })
+            })?;
+
+        let cycle = self.path[start..]
+            .iter()
+            .map(|(c, _)| *c)
+            .chain([call_id])
+            .collect();
+        Some(cycle)
+    }
+
+    /// Get the task call already populated with the given identity, if any,
+    /// otherwise recording this task call as the one to merge identical ones
+    /// into.
+    fn merge(&mut self, call_id: TaskCallId, identity: CallIdentity) -> Option<TaskCallId> {
+        if let Some(&existing) = self.populated.get(&identity) {
+            return Some(existing);
+        }
+        self.populated.insert(identity, call_id);
+        None
+    }
+}
+
+/// Which tasks should be run regardless of whether or not they are dirty.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ForceMode {
+    /// Only run dirty tasks.
+    #[default]
+    None,
+    /// Run every task.
+    All,
+    /// Run only the tasks with the given names, along with any other dirty
+    /// tasks.
+    Tasks(Vec<String>),
+}
+
+impl ForceMode {
+    fn is_forced(&self, task_name: &str) -> bool {
+        match self {
+            ForceMode::None => false,
+            ForceMode::All => true,
+            ForceMode::Tasks(tasks) => tasks.iter().any(|t| t == task_name),
+        }
+    }
+}
+
+/// Everything needed to check whether task calls are dirty, which can be moved
+/// into spawned tasks.
+#[derive(Debug, Clone)]
+struct DirtyCheck {
+    force: ForceMode,
+    content_hash: bool,
+    database: Arc<Mutex<BuildDatabase>>,
+    project_root: PathBuf,
+}
+
+impl DirtyCheck {
+    /// Check whether a task call is dirty, given the name of its task, its
+    /// key in the [`BuildDatabase`] (see [`Engine::call_key`]) and its
+    /// fingerprint.
+    fn check(
+        &self,
+        task_name: &str,
+        metadata: &TaskCallMetadata,
+        key: &str,
+        fingerprint: &str,
+    ) -> EngineResult<Dirtiness> {
+        if self.force.is_forced(task_name) {
+            return Ok(Dirtiness::Forced);
+        }
+
+        // don't hold the lock while checking files, as hashing them may take a while
+        let record = self.database.lock().get(key).cloned();
+        let record = record.as_ref();
+        if self.content_hash {
+            check_dirty_hashed(metadata, &self.project_root, fingerprint, record)
+        } else {
+            check_dirty(metadata, &self.project_root).map(|dirtiness| {
+                if dirtiness.is_dirty() {
+                    dirtiness
+                } else {
+                    check_fingerprint(fingerprint, record).unwrap_or(dirtiness)
+                }
+            })
+        }
+        .map_err(|err| EngineError::internal(format!("failed to check dirty status: {err}")))
+    }
+}
+
+/// The outcome of a single task call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TaskStatus {
+    Succeeded,
+    Skipped,
+    Failed,
+    Interrupted,
+    /// The task call ran for longer than its timeout and was stopped.
+    TimedOut,
+}
+
+/// The result of a spawned task call.
+#[derive(Debug)]
+struct TaskResult {
+    call_id: TaskCallId,
+    /// See [`TaskHandle::generation`].
+    generation: usize,
+    status: TaskStatus,
+    /// The error that caused the task call to fail, if any.
+    error: Option<ShellError>,
+    /// How long the task call ran for, including any retries.
+    duration: Duration,
+}
+
+impl TaskResult {
+    fn new(call_id: TaskCallId, generation: usize, status: TaskStatus) -> Self {
+        Self {
+            call_id,
+            generation,
+            status,
+            error: None,
+            duration: Duration::ZERO,
+        }
+    }
+}
+
+/// A handle to a running task call.
+#[derive(Debug)]
+struct TaskHandle {
+    abort: AbortHandle,
+    ctrlc: Arc<AtomicBool>,
+    /// Unique to each spawned task, so that results from tasks that have since
+    /// been aborted can be identified.
+    generation: usize,
+    start_time: Instant,
+    /// See [`Engine::timeout`].
+    timeout: Option<Duration>,
+    /// The captured output of the current attempt of the task call when quiet,
+    /// so that it can still be shown if the task call times out.
+    output: Arc<Mutex<Option<Arc<File>>>>,
+}
+
+/// What the engine was woken up by while running a build.
+enum Wakeup {
+    TaskFinished(TaskCallId, TaskStatus, Option<ShellError>),
+    FilesChanged(Vec<PathBuf>),
+}
+
+/// Statistics for a single build, reported once the build has finished.
+#[derive(Debug)]
+struct BuildStats {
+    start_time: Instant,
+    succeeded: usize,
+    skipped: usize,
+    failed: usize,
+    /// The task calls that have failed, along with their errors (if any).
+    failures: Vec<(TaskCallId, Option<ShellError>)>,
+    /// The task calls that have timed out, along with how long they ran for.
+    timeouts: Vec<(TaskCallId, Duration)>,
+    /// The task calls that were stopped (or interrupted) while running.
+    cancelled: Vec<TaskCallId>,
+    /// The task calls that never started as a result of a failure.
+    not_started: Vec<TaskCallId>,
+}
+
+impl BuildStats {
+    fn new() -> Self {
+        Self {
+            start_time: Instant::now(),
+            succeeded: 0,
+            skipped: 0,
+            failed: 0,
+            failures: Vec::new(),
+            timeouts: Vec::new(),
+            cancelled: Vec::new(),
+            not_started: Vec::new(),
+        }
+    }
+
+    fn summary(&self) -> Event {
+        Event::BuildSummary {
+            success: self.failed == 0 && self.cancelled.is_empty(),
+            duration_ms: duration_ms(self.start_time.elapsed()),
+            succeeded: self.succeeded,
+            skipped: self.skipped,
+            failed: self.failed,
+            cancelled: self.cancelled.len(),
+            not_started: self.not_started.len(),
+        }
+    }
 }
 
 pub struct Engine {
     project: Project,
-    _options: EngineOptions,
+    options: EngineOptions,
+    events: EventEmitter,
+    database: Arc<Mutex<BuildDatabase>>,
     state: Arc<RwLock<State>>,
     engine_state: EngineState,
     stack: Stack,
-    task_pool: JoinSet<Result<(TaskCallId, bool), EngineError>>,
-    handles: Mutex<HashMap<TaskCallId, (AbortHandle, Arc<AtomicBool>)>>,
+    task_pool: JoinSet<Result<TaskResult, EngineError>>,
+    handles: Mutex<HashMap<TaskCallId, TaskHandle>>,
+    next_generation: usize,
+    /// Interrupts from the user, listened for while running (see
+    /// [`Engine::run`]).
+    interrupts: Option<UnboundedReceiver<()>>,
 }
 
 impl Engine {
@@ -51,19 +335,30 @@ impl Engine {
         #[cfg(windows)]
         nu_ansi_term::enable_ansi_support().expect("Failed to initialize ANSI support");
 
-        let state = Arc::new(RwLock::new(State::new()));
+        let state = Arc::new(RwLock::new(State::new(project.project_root().clone())));
 
         let engine_state = create_engine_state(state.clone());
         let stack = create_stack(project.project_root());
 
+        let events = EventEmitter::new(options.json);
+        let database = BuildDatabase::open(&project).unwrap_or_else(|message| {
+            events.log_warning("failed to read build database", &message);
+            BuildDatabase::new(&project)
+        });
+        let database = Arc::new(Mutex::new(database));
+
         let mut engine = Self {
             project,
-            _options: options,
+            events,
+            database,
+            options,
             state,
             engine_state,
             stack,
             task_pool: JoinSet::new(),
             handles: Mutex::new(HashMap::new()),
+            next_generation: 0,
+            interrupts: None,
         };
 
         engine.load_script()?;
@@ -81,13 +376,13 @@ impl Engine {
         // report parse errors in working set, but do not discard as the working state
         // is intended to represent such invalid states
         for error in &working_set.parse_errors {
-            report_error(working_set, error);
+            self.events.report_error(None, working_set, error);
         }
 
         // report errors emitted by quake, removing them so that the engine may continue
         // to function if recovery is desirable
         for error in state.errors.drain(..) {
-            report_error(working_set, &*error);
+            self.events.report_error(None, working_set, &*error);
         }
 
         true
@@ -101,10 +396,22 @@ impl Engine {
         if error.is_quake_internal() {
             self.report_errors_new();
         } else {
-            report_error_new(&self.engine_state, error);
+            self.events
+                .report_error_new(None, &self.engine_state, error);
         }
     }
 
+    /// Reset all internal state and reload the project's build script.
+    fn reload_script(&mut self) -> EngineResult<()> {
+        self.abort_all();
+
+        self.state = Arc::new(RwLock::new(State::new(self.project.project_root().clone())));
+        self.engine_state = create_engine_state(self.state.clone());
+        self.stack = create_stack(self.project.project_root());
+
+        self.load_script()
+    }
+
     /// Load and evaluate the project's build script.
     fn load_script(&mut self) -> EngineResult<()> {
         let build_script = self.project.build_script();
@@ -129,6 +436,13 @@ impl Engine {
             }
         }
 
+        // expose the job limit to tasks, now that the build script may have set it
+        let jobs = self.jobs();
+        self.stack.add_env_var(
+            JOBS_ENV.to_owned(),
+            Value::string(jobs.to_string(), Span::unknown()),
+        );
+
         Ok(())
     }
 
@@ -178,133 +492,804 @@ impl Engine {
         RwLockReadGuard::map(self.state.read(), |s| &s.metadata)
     }
 
-    pub fn run(&mut self, task_name: &str, arguments: &str) -> EngineResult<()> {
-        if !arguments.is_empty() {
-            log_warning!("argument passing from the command line is currently unsupported");
+    /// Describe every task defined by the build script, in order of definition.
+    pub fn describe_tasks(&self) -> Vec<TaskInfo> {
+        let config = self.engine_state.get_config();
+        self.metadata()
+            .task()
+            .map(|task| {
+                // the signature of the task is stored on both of its blocks
+                let signature = (task.decl_body.or(task.run_body))
+                    .map(|block_id| &*self.engine_state.get_block(block_id).signature);
+                TaskInfo::new(task, signature, config)
+            })
+            .collect()
+    }
+
+    /// Describe a single task (see [`Engine::describe_tasks`]).
+    pub fn describe_task(&self, task_name: &str) -> EngineResult<TaskInfo> {
+        if let Err(err) = self.metadata().find_task(task_name, None) {
+            self.events
+                .report_error_new(None, &self.engine_state, &*err);
+            return Err(EngineError::EvalFailed);
         }
 
-        let arguments = vec![]; // TODO parse arguments instead
+        Ok(self
+            .describe_tasks()
+            .into_iter()
+            .find(|t| t.name == task_name)
+            .unwrap())
+    }
 
-        let Some(call_id) = self
-            .populate_metadata_for_call(task_name, arguments)
-            .inspect_err(|err| report_error_new(&self.engine_state, &**err))
-            .ok()
-            .flatten()
-        else {
-            return Err(EngineError::EvalFailed);
+    /// The maximum number of tasks to run at once.
+    ///
+    /// This is taken from the [engine options](EngineOptions::jobs) if set,
+    /// otherwise from the build script (with `default-jobs`), and otherwise
+    /// defaults to the available parallelism of the system.
+    pub fn jobs(&self) -> NonZeroUsize {
+        self.options
+            .jobs
+            .or(self.state.read().jobs)
+            .unwrap_or_else(|| available_parallelism().unwrap_or(NonZeroUsize::MIN))
+    }
+
+    /// The maximum amount of time a task call may run for, if any.
+    ///
+    /// This is taken from the [engine options](EngineOptions::timeout) if set,
+    /// otherwise from the task (with `--timeout`), and otherwise from the build
+    /// script (with `default-timeout`).
+    fn timeout(&self, call_id: TaskCallId) -> Option<Duration> {
+        let state = self.state.read();
+        let task_id = state.metadata.get_task_call(call_id).unwrap().task_id;
+        self.options
+            .timeout
+            .or(state.metadata.get_task(task_id).unwrap().flags.timeout)
+            .or(state.timeout)
+    }
+
+    /// Run one or more task calls, each given as the name of the task and its
+    /// arguments, as a single build.
+    ///
+    /// Dependencies shared between the task calls are only run once, and the
+    /// task calls are otherwise run in the order given (see [`RunGraph::new`]).
+    pub fn run(&mut self, calls: &[(String, String)]) -> EngineResult<()> {
+        self.check_forced_tasks()?;
+
+        // the number of tasks running at once is limited by the run graph (see
+        // `RunGraph::ready`), not by the number of worker threads
+        let runtime = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .map_err(|_| EngineError::internal("failed to create runtime"))?;
+        let guard = runtime.enter();
+
+        // listen for interrupts throughout, so that none are missed between builds
+        // (e.g. while the build script is being reloaded)
+        let (sender, receiver) = unbounded_channel();
+        runtime.spawn(listen_for_interrupts(sender));
+        self.interrupts = Some(receiver);
+
+        let result = if self.options.watch {
+            runtime.block_on(self.watch_calls(calls))
+        } else {
+            self.prepare_calls(calls)
+                .and_then(|call_ids| runtime.block_on(self.run_calls(&call_ids, None)))
         };
 
-        let build_tree = generate_run_tree(call_id, &self.metadata());
+        self.interrupts = None;
 
-        let mut task_iter = build_tree.flatten().into_iter().peekable();
+        // don't wait for aborted tasks to finish if the user is trying to exit, and
+        // otherwise only wait for so long, as they may never finish (e.g. after
+        // timing out)
+        drop(guard);
+        if let Err(EngineError::Interrupted) = result {
+            runtime.shutdown_background();
+        } else {
+            runtime.shutdown_timeout(SHUTDOWN_TIMEOUT);
+        }
 
-        macro_rules! spawn_tasks {
-            () => {
-                // spawn as many tasks as possible
-                while let Some(node) = task_iter.peek() {
-                    // ensure no children are still running
-                    {
-                        let handles = self.handles.lock();
-                        if node
-                            .children
-                            .iter()
-                            .any(|c| handles.contains_key(&c.call_id))
-                        {
-                            break;
-                        }
-                    }
+        result
+    }
+
+    /// Parse task calls from the command line (see [`Engine::run`]) and
+    /// evaluate the declaration bodies of them and all of their dependencies,
+    /// without running them.
+    ///
+    /// Afterwards, the metadata of the task calls and their dependencies (such
+    /// as their expanded sources and artifacts) can be found in
+    /// [`Engine::metadata`].
+    ///
+    /// Identical task calls are merged, so the returned task calls are unique.
+    pub fn prepare_calls(&mut self, calls: &[(String, String)]) -> EngineResult<Vec<TaskCallId>> {
+        let mut parsed = Vec::with_capacity(calls.len());
+        for (task_name, arguments) in calls {
+            let (span, arguments) = self
+                .parse_command_line(task_name, arguments)
+                .ok_or(EngineError::ParseFailed)?;
+            parsed.push((task_name, span, arguments));
+        }
+
+        // dependencies are shared between all of the task calls
+        let mut visited = VisitedCalls::default();
+        let mut call_ids = Vec::with_capacity(parsed.len());
+        for (task_name, span, arguments) in parsed {
+            let call_id = self
+                .populate_metadata_for_call(task_name, span, arguments, &mut visited)
+                .inspect_err(|err| {
+                    self.events
+                        .report_error_new(None, &self.engine_state, &**err)
+                })
+                .ok()
+                .flatten()
+                .ok_or(EngineError::EvalFailed)?;
+
+            if !call_ids.contains(&call_id) {
+                call_ids.push(call_id);
+            }
+        }
 
-                    // advance the iterator and spawn the task
-                    let node = task_iter.next().unwrap();
-                    self.spawn_task(node)?;
+        Ok(call_ids)
+    }
 
-                    // don't add any more tasks if this one is blocking
+    /// Plan a build of task calls (see [`Engine::run`]) without running any of
+    /// them, determining the order they would run in and which would be
+    /// skipped.
+    ///
+    /// The declaration bodies of the task calls are evaluated as they would be
+    /// for a build (see [`Engine::prepare_calls`]), but no run bodies are.
+    pub fn plan(&mut self, calls: &[(String, String)]) -> EngineResult<Plan> {
+        self.check_forced_tasks()?;
+
+        let call_ids = self.prepare_calls(calls)?;
+        let graph = RunGraph::new(&call_ids, &self.metadata());
+        let stages = graph.stages(self.jobs().get(), |c| self.is_concurrent(c));
+
+        let dirty_check = self.dirty_check();
+        let mut planned = Vec::with_capacity(graph.order().len());
+        for (stage, call_ids) in stages.into_iter().enumerate() {
+            for call_id in call_ids {
+                let fingerprint = fingerprint_task_call(call_id, &self.engine_state);
+
+                let task = self.task_name(call_id);
+                let key = self.call_key(call_id);
+                let dirtiness = {
                     let metadata = self.metadata();
-                    let call = metadata.get_task_call(node.call_id).unwrap();
-                    let concurrent = metadata.get_task(call.task_id).unwrap().flags.concurrent;
-                    if !concurrent {
-                        break;
-                    }
+                    let call_metadata = metadata.task_call_metadata(call_id).unwrap();
+                    dirty_check.check(&task, &call_metadata, &key, &fingerprint)?
+                };
+
+                planned.push(PlannedCall {
+                    call_id,
+                    task,
+                    call: key,
+                    stage,
+                    concurrent: self.is_concurrent(call_id),
+                    dependencies: graph.dependencies(call_id).to_vec(),
+                    run: dirtiness.is_dirty(),
+                    dirtiness,
+                });
+            }
+        }
+
+        Ok(Plan { calls: planned })
+    }
+
+    /// Generate the dependency graph of task calls (see [`Engine::run`]),
+    /// including those of their transitive dependencies, without running any
+    /// of them.
+    pub fn graph(&mut self, calls: &[(String, String)]) -> EngineResult<CallGraph> {
+        let call_ids = self.prepare_calls(calls)?;
+        let graph = RunGraph::new(&call_ids, &self.metadata());
+
+        let calls = graph
+            .order()
+            .iter()
+            .map(|&call_id| {
+                let subtask = {
+                    let metadata = self.metadata();
+                    let task_id = metadata.get_task_call(call_id).unwrap().task_id;
+                    metadata.get_task(task_id).unwrap().is_subtask()
+                };
+                CallNode {
+                    call_id,
+                    task: self.task_name(call_id),
+                    call: self.call_key(call_id),
+                    root: graph.roots().contains(&call_id),
+                    subtask,
                 }
-            };
+            })
+            .collect();
+        let edges = graph
+            .order()
+            .iter()
+            .flat_map(|&from| {
+                (graph.dependencies(from).iter()).map(move |&to| CallEdge { from, to })
+            })
+            .collect();
+
+        Ok(CallGraph { calls, edges })
+    }
+
+    /// Ensure that every task forced to run (see [`ForceMode::Tasks`]) exists.
+    fn check_forced_tasks(&self) -> EngineResult<()> {
+        if let ForceMode::Tasks(tasks) = &self.options.force {
+            for name in tasks {
+                if let Err(err) = self.metadata().find_task(name, None) {
+                    self.events
+                        .report_error_new(None, &self.engine_state, &*err);
+                    return Err(EngineError::EvalFailed);
+                }
+            }
         }
 
-        let runtime =
-            Runtime::new().map_err(|_| EngineError::internal("failed to create runtime"))?;
-        let _rt = runtime.enter();
+        Ok(())
+    }
 
-        // run the main loop
-        runtime.block_on(async move {
-            // initialize first task(s)
-            spawn_tasks!();
+    /// Run task calls, re-running them whenever any of the sources declared in
+    /// their run graph are changed. The build script is reloaded whenever it
+    /// is changed.
+    ///
+    /// This will only return if an internal error occurs.
+    async fn watch_calls(&mut self, calls: &[(String, String)]) -> EngineResult<()> {
+        let build_script = self.project.build_script().clone();
 
-            // join tasks and continue to add more
-            while let Some(result) = self.task_pool.join_next().await {
-                let (task_call_id, success) = match result {
-                    Ok(Ok(result)) => result,
-                    Ok(Err(error)) => {
-                        self.abort_all();
-                        return Err(error);
+        let mut watcher = Watcher::new(self.events)?;
+        let mut reload = false;
+
+        loop {
+            watcher.unwatch_all();
+            watcher.watch([build_script.clone()]);
+
+            let call_ids = if !reload || self.reload_script().is_ok() {
+                self.prepare_calls(calls).ok()
+            } else {
+                None
+            };
+
+            // run the tasks until the build script changes, otherwise wait for the errors
+            // in the build script to be fixed
+            match call_ids {
+                Some(call_ids) => self.run_calls(&call_ids, Some(&mut watcher)).await?,
+                None => {
+                    self.events
+                        .log_info("watching", "waiting for changes to the build script");
+                    loop {
+                        tokio::select! {
+                            changed = watcher.changed() => {
+                                if changed.contains(&build_script) {
+                                    break;
+                                }
+                            }
+                            () = wait_for_interrupt(&mut self.interrupts) => {
+                                return Err(EngineError::Interrupted);
+                            }
+                        }
                     }
-                    // join error
-                    Err(err) => {
-                        if err.is_cancelled() {
+                }
+            }
+
+            self.events.log_info("reloading", "build script changed");
+            reload = true;
+        }
+    }
+
+    /// Run the combined run graph for task calls to completion.
+    ///
+    /// Each task call is started as soon as all of its dependencies have
+    /// finished, so independent task calls may run in parallel (unless their
+    /// tasks are not marked as concurrent).
+    ///
+    /// If a watcher is provided, the sources declared by the tasks in the run
+    /// graph are watched, and the affected task calls (along with those that
+    /// depend on them) are re-run whenever they change. Task failures are then
+    /// reported but do not stop the engine, and this instead returns `Ok`
+    /// once the build script has changed.
+    async fn run_calls(
+        &mut self,
+        call_ids: &[TaskCallId],
+        mut watcher: Option<&mut Watcher>,
+    ) -> EngineResult<()> {
+        let graph = RunGraph::new(call_ids, &self.metadata());
+
+        for &call_id in graph.order() {
+            self.events.emit(Event::TaskScheduled {
+                call_id,
+                task: self.task_name(call_id),
+            });
+        }
+
+        // watch the sources of every task, excluding any files produced by the build
+        let mut sources: HashMap<PathBuf, Vec<TaskCallId>> = HashMap::new();
+        if let Some(watcher) = &mut watcher {
+            let project_root = self.project.project_root();
+            let mut artifacts = HashSet::new();
+
+            let metadata = self.metadata();
+            for &call_id in graph.order() {
+                let call_metadata = metadata.task_call_metadata(call_id).unwrap();
+                for source in &call_metadata.sources {
+                    let entry = sources.entry(project_root.join(source)).or_default();
+                    entry.push(call_id);
+                }
+                artifacts.extend(call_metadata.artifacts.iter().map(|a| project_root.join(a)));
+            }
+            sources.retain(|path, _| !artifacts.contains(path));
+
+            watcher.watch(sources.keys().cloned());
+        }
+
+        // task calls yet to be spawned, and those that have finished successfully
+        let mut pending: HashSet<TaskCallId> = graph.order().iter().copied().collect();
+        let mut finished: HashSet<TaskCallId> = HashSet::new();
+
+        let mut stats = BuildStats::new();
+        let mut idle = false;
+        let mut interrupted = false;
+
+        loop {
+            self.spawn_ready(&graph, &mut pending, &finished)?;
+
+            // the build has finished (or was stopped)
+            if self.task_pool.is_empty() && !idle {
+                self.events.emit(stats.summary());
+
+                let failure = self.build_failure(&graph, &stats);
+                if let Some(error) = &failure
+                    && !self.events.is_json()
+                {
+                    self.events
+                        .report_error_new(None, &self.engine_state, error);
+                }
+
+                if interrupted {
+                    return Err(EngineError::Interrupted);
+                }
+                if watcher.is_none() {
+                    return failure.map_or(Ok(()), |error| Err(error.into()));
+                }
+
+                self.events.log_info("watching", "waiting for changes");
+                idle = true;
+            }
+
+            let next_timeout = self.next_timeout();
+            let wakeup = tokio::select! {
+                Some(result) = self.task_pool.join_next() => {
+                    let result = match result {
+                        Ok(Ok(result)) => result,
+                        Ok(Err(error)) => {
+                            self.abort_all();
+                            return Err(error);
+                        }
+                        // join error
+                        Err(err) => {
+                            if err.is_cancelled() {
+                                continue;
+                            }
+
+                            return Err(EngineError::internal(format!(
+                                "failed to join task: {err}"
+                            )));
+                        }
+                    };
+                    let TaskResult { call_id, generation, status, error, duration } = result;
+
+                    // ignore results from tasks that have since been aborted
+                    {
+                        let mut handles = self.handles.lock();
+                        if handles.get(&call_id).map(|h| h.generation) != Some(generation) {
                             continue;
                         }
+                        handles.remove(&call_id);
+                    }
 
-                        return Err(EngineError::internal(format!("failed to join task: {err}")));
+                    // only reported here, as tasks that have timed out (or have otherwise been
+                    // aborted) may still finish
+                    if status != TaskStatus::Skipped {
+                        self.events.emit(Event::TaskFinished {
+                            call_id,
+                            task: self.task_name(call_id),
+                            success: status == TaskStatus::Succeeded,
+                            duration_ms: duration_ms(duration),
+                        });
                     }
-                };
 
-                // ensure the handle is removed
-                // FIXME remove handle in every brnach instead
-                self.handles.lock().remove(&task_call_id);
+                    Wakeup::TaskFinished(call_id, status, error)
+                }
+                (call_id, elapsed) = wait_for_timeout(next_timeout) => {
+                    self.report_timeout(call_id, elapsed);
+                    self.abort_task(call_id);
+                    stats.timeouts.push((call_id, elapsed));
+
+                    Wakeup::TaskFinished(call_id, TaskStatus::TimedOut, None)
+                }
+                () = wait_for_interrupt(&mut self.interrupts) => {
+                    // stop immediately if interrupted again, or if there is nothing to wait for
+                    if interrupted || idle {
+                        self.abort_all();
+                        return Err(EngineError::Interrupted);
+                    }
 
-                if !success {
-                    self.abort_all();
+                    interrupted = true;
+                    stats.not_started.extend(pending.drain());
+                    self.interrupt_all();
+                    continue;
+                }
+                changed = wait_for_changes(&mut watcher) => Wakeup::FilesChanged(changed),
+            };
 
-                    let task_name = {
-                        let metadata = self.metadata();
-                        let task_id = metadata.get_task_call(task_call_id).unwrap().task_id;
-                        metadata.get_task(task_id).unwrap().name.item.clone()
-                    };
+            let changed = match wakeup {
+                Wakeup::TaskFinished(call_id, status, error) => {
+                    match status {
+                        TaskStatus::Succeeded => {
+                            stats.succeeded += 1;
+                            finished.insert(call_id);
+                        }
+                        TaskStatus::Skipped => {
+                            stats.skipped += 1;
+                            finished.insert(call_id);
+                        }
+                        TaskStatus::Failed | TaskStatus::TimedOut if self.options.keep_going => {
+                            stats.failed += 1;
+                            if status == TaskStatus::Failed {
+                                stats.failures.push((call_id, error));
+                            }
+
+                            // tasks depending on the failed task can no longer run, but any
+                            // others can continue
+                            for dependent in graph.with_dependents(call_id) {
+                                if pending.remove(&dependent) {
+                                    stats.not_started.push(dependent);
+                                }
+                            }
+                        }
+                        TaskStatus::Failed | TaskStatus::TimedOut | TaskStatus::Interrupted => {
+                            match status {
+                                TaskStatus::Interrupted => stats.cancelled.push(call_id),
+                                TaskStatus::Failed => {
+                                    stats.failed += 1;
+                                    stats.failures.push((call_id, error));
+                                }
+                                _ => stats.failed += 1,
+                            }
+
+                            // stop the build, cancelling any other running tasks, unless they
+                            // have already been interrupted (in which case they are left to exit
+                            // on their own, unless interrupted again)
+                            if !interrupted {
+                                stats.cancelled.extend(self.handles.lock().keys().copied());
+                                stats.not_started.extend(pending.drain());
+                                self.abort_all();
+                            }
+                        }
+                    }
 
-                    return Err(EngineError::TaskFailed { task_name });
+                    continue;
                 }
+                Wakeup::FilesChanged(changed) => changed,
+            };
 
-                spawn_tasks!();
+            // reload everything if the build script has changed
+            if changed.contains(self.project.build_script()) {
+                self.abort_all();
+                return Ok(());
+            }
+
+            let changed_calls = changed
+                .iter()
+                .filter_map(|path| sources.get(path))
+                .flatten()
+                .copied()
+                .collect::<HashSet<_>>();
+            if changed_calls.is_empty() {
+                continue;
+            }
+
+            // start a new build if the last one has finished
+            if idle {
+                stats = BuildStats::new();
+                idle = false;
+            }
+
+            // the changed tasks must be re-run, along with any tasks depending on them
+            let mut rerun = changed_calls
+                .into_iter()
+                .flat_map(|call_id| graph.with_dependents(call_id))
+                .collect::<HashSet<_>>();
+            for &call_id in &rerun {
+                self.abort_task(call_id);
+                finished.remove(&call_id);
+            }
+
+            // as well as any of their dependencies that have not yet finished (e.g. due to
+            // an earlier failure) and are not still running
+            let missing = {
+                let handles = self.handles.lock();
+                rerun
+                    .iter()
+                    .flat_map(|&call_id| graph.with_dependencies(call_id))
+                    .filter(|c| !finished.contains(c) && !handles.contains_key(c))
+                    .collect::<Vec<_>>()
+            };
+            rerun.extend(missing);
+            pending.extend(&rerun);
+
+            for &call_id in graph.order() {
+                if rerun.contains(&call_id) {
+                    self.events.emit(Event::TaskScheduled {
+                        call_id,
+                        task: self.task_name(call_id),
+                    });
+                }
             }
 
-            Ok(())
+            self.events.log_info(
+                "sources changed",
+                &format!("re-running {} task(s)", pending.len()),
+            );
+        }
+    }
+
+    /// Collect every task call that did not finish during a build, along with
+    /// the errors of those that failed, or `None` if the build succeeded.
+    fn build_failure(&self, graph: &RunGraph, stats: &BuildStats) -> Option<errors::BuildFailed> {
+        if stats.failed == 0 && stats.cancelled.is_empty() {
+            return None;
+        }
+
+        let describe = |call_id| {
+            let span = self.metadata().get_task_call(call_id).unwrap().span;
+            (self.task_name(call_id), span)
+        };
+
+        let failed = stats.failures.iter().map(|(call_id, error)| {
+            let (task, span) = describe(*call_id);
+            errors::TaskCallFailure::Failed {
+                task,
+                span,
+                error: error.clone(),
+            }
+        });
+        let timed_out = stats.timeouts.iter().map(|&(call_id, elapsed)| {
+            let (task, span) = describe(call_id);
+            errors::TaskCallFailure::TimedOut {
+                task,
+                span,
+                elapsed,
+            }
+        });
+        // list other task calls in order of execution
+        let cancelled = graph
+            .order()
+            .iter()
+            .filter(|&c| stats.cancelled.contains(c))
+            .map(|&call_id| {
+                let (task, span) = describe(call_id);
+                errors::TaskCallFailure::Cancelled { task, span }
+            });
+        let not_started = graph
+            .order()
+            .iter()
+            .filter(|&c| stats.not_started.contains(c))
+            .map(|&call_id| {
+                let (task, span) = describe(call_id);
+                errors::TaskCallFailure::NotStarted { task, span }
+            });
+
+        Some(errors::BuildFailed {
+            tasks: failed
+                .chain(timed_out)
+                .chain(cancelled)
+                .chain(not_started)
+                .collect(),
         })
     }
 
+    /// Spawn all pending task calls that are ready to start (see
+    /// [`RunGraph::ready`]), in order of execution.
+    fn spawn_ready(
+        &mut self,
+        graph: &RunGraph,
+        pending: &mut HashSet<TaskCallId>,
+        finished: &HashSet<TaskCallId>,
+    ) -> EngineResult<()> {
+        let running = self.handles.lock().keys().copied().collect::<Vec<_>>();
+        let ready = graph.ready(pending, finished, &running, self.jobs().get(), |c| {
+            self.is_concurrent(c)
+        });
+
+        for call_id in ready {
+            pending.remove(&call_id);
+            self.spawn_task(call_id)?;
+        }
+
+        Ok(())
+    }
+
+    fn dirty_check(&self) -> DirtyCheck {
+        DirtyCheck {
+            force: self.options.force.clone(),
+            content_hash: self.options.content_hash,
+            database: self.database.clone(),
+            project_root: self.project.project_root().clone(),
+        }
+    }
+
+    /// Check whether the task of a task call may run concurrently with others.
+    fn is_concurrent(&self, call_id: TaskCallId) -> bool {
+        let metadata = self.metadata();
+        let task_id = metadata.get_task_call(call_id).unwrap().task_id;
+        metadata.get_task(task_id).unwrap().flags.concurrent
+    }
+
+    /// Get a key identifying a task call across invocations, used to look up
+    /// its state in the [`BuildDatabase`].
+    ///
+    /// This is the task along with the values of its arguments (see
+    /// [`describe_task_call`]).
+    fn call_key(&self, call_id: TaskCallId) -> String {
+        describe_task_call(call_id, &self.engine_state)
+    }
+
+    /// Get the name of the task corresponding to a task call.
+    fn task_name(&self, call_id: TaskCallId) -> String {
+        let metadata = self.metadata();
+        let task_id = metadata.get_task_call(call_id).unwrap().task_id;
+        metadata.get_task(task_id).unwrap().name.item.clone()
+    }
+
+    /// Parse the arguments to a task call provided from the command line,
+    /// checking them against the signature of the task.
+    ///
+    /// Returns the span of the task name and the parsed arguments, or `None` if
+    /// any errors occurred (in which case they will have already been
+    /// reported).
+    fn parse_command_line(
+        &mut self,
+        task_name: &str,
+        arguments: &str,
+    ) -> Option<(Span, Vec<Argument>)> {
+        let (result, delta) = {
+            let mut working_set = StateWorkingSet::new(&self.engine_state);
+
+            // add the call as a virtual file so that diagnostics may point to it
+            let source = format!("{task_name} {arguments}");
+            let span_offset = working_set.next_span_start();
+            working_set.add_file(COMMAND_LINE_FILENAME.to_owned(), source.as_bytes());
+            let head = Span::new(span_offset, span_offset + task_name.len());
+
+            // find the `DependsTask` decl, which shares its signature with the task
+            let depends_decl_id = {
+                let state = self.state.read();
+                state
+                    .metadata
+                    .find_task(task_name, Some(head))
+                    .and_then(|task| {
+                        task.depends_decl_id.ok_or_else(|| {
+                            state.metadata.task_not_found(task_name, Some(head)).into()
+                        })
+                    })
+            };
+            let depends_decl_id = match depends_decl_id {
+                Ok(decl_id) => decl_id,
+                Err(error) => {
+                    self.events.report_error(None, &working_set, &*error);
+                    return None;
+                }
+            };
+
+            // split the arguments into spans (skipping the task name) and parse them as
+            // a call to the task
+            let (tokens, error) = lex(source.as_bytes(), span_offset, &[], &[], true);
+            if let Some(error) = error {
+                working_set.error(error);
+            }
+            let arg_spans = tokens.iter().skip(1).map(|t| t.span).collect::<Vec<_>>();
+            let call =
+                parse_internal_call(&mut working_set, head, &arg_spans, depends_decl_id).call;
+
+            // report missing and unknown arguments
+            if self.report_errors(&working_set) {
+                return None;
+            }
+
+            ((head, call.arguments), working_set.render())
+        };
+
+        // merge the virtual file so that argument expressions may be evaluated
+        if let Err(err) = self.engine_state.merge_delta(delta) {
+            self.report_shell_error(&err);
+            return None;
+        }
+
+        Some(result)
+    }
+
+    /// Register a task call from the command line and populate its metadata
+    /// (see [`Engine::populate_metadata_for_call_id`]).
+    ///
+    /// Returns the ID of the task call to run, which is that of an identical
+    /// task call if one has already been populated with `visited`.
     fn populate_metadata_for_call(
         &mut self,
         task_name: &str,
+        span: Span,
         arguments: Vec<Argument>,
+        visited: &mut VisitedCalls,
     ) -> DiagResult<Option<TaskCallId>> {
-        let call_id = {
-            let mut state = self.state.write();
-
-            let task_id = state.metadata.find_task_id(task_name, None)?;
-            state
-                .metadata
-                .register_task_call(task_id, Span::unknown(), arguments, Vec::new())
-                .unwrap()
+        let (task_id, depends_decl_id) = {
+            let metadata = self.metadata();
+            let task_id = metadata.find_task_id(task_name, Some(span))?;
+            (task_id, metadata.get_task(task_id).unwrap().depends_decl_id)
         };
 
-        if let Err(error) = self.populate_metadata_for_call_id(call_id) {
-            self.report_shell_error(&error);
-            return Ok(None);
-        }
+        // the `DependsTask` decl shares its signature with the task (and exists for any
+        // task parsed from the command line)
+        let signature = self
+            .engine_state
+            .get_decl(depends_decl_id.unwrap())
+            .signature();
+        let arguments =
+            match eval_arguments(&arguments, &signature, &self.engine_state, &mut self.stack) {
+                Ok(arguments) => arguments,
+                Err(error) => {
+                    self.report_shell_error(&error);
+                    return Ok(None);
+                }
+            };
 
-        Ok(Some(call_id))
+        let call_id = self
+            .state
+            .write()
+            .metadata
+            .register_task_call(task_id, span, arguments, Vec::new())
+            .unwrap();
+
+        match self.populate_metadata_for_call_id(call_id, visited) {
+            Ok(call_id) => Ok(call_id),
+            Err(error) => {
+                self.report_shell_error(&error);
+                Ok(None)
+            }
+        }
     }
 
-    fn populate_metadata_for_call_id(&mut self, call_id: TaskCallId) -> ShellResult<bool> {
+    /// Evaluate the declaration bodies of a task call and its dependencies.
+    ///
+    /// Dependencies identical to a task call that has already been populated
+    /// (unless their task is marked with `--no-dedup`) are replaced with that
+    /// task call, so that they are only run once. Dependency cycles are
+    /// reported as errors, as are dependencies nested deeper than
+    /// [`MAX_DEPENDENCY_DEPTH`].
+    ///
+    /// Returns the ID of the task call to depend upon in place of this one, or
+    /// `None` if evaluation was unsuccessful.
+    fn populate_metadata_for_call_id(
+        &mut self,
+        call_id: TaskCallId,
+        visited: &mut VisitedCalls,
+    ) -> ShellResult<Option<TaskCallId>> {
+        let (task_id, no_dedup) = {
+            let metadata = self.metadata();
+            let task_id = metadata.get_task_call(call_id).unwrap().task_id;
+            (task_id, metadata.get_task(task_id).unwrap().flags.no_dedup)
+        };
+        let fingerprint = fingerprint_task_call(call_id, &self.engine_state);
+        let identity = (task_id, fingerprint);
+
+        if let Some(cycle) = visited.find_cycle(call_id, &identity) {
+            let error = self.dependency_cycle_error(&cycle);
+            return State::capture_errors_in_shell(&self.engine_state, |_| Err(error.into()));
+        }
+
+        if !no_dedup {
+            if let Some(existing) = visited.merge(call_id, identity.clone()) {
+                return Ok(Some(existing));
+            }
+        }
+
         if !eval_task_decl_body(call_id, &self.engine_state, &mut self.stack)? {
-            return Ok(false);
+            return Ok(None);
         }
 
         // copy out dependencies to avoid deadlock between readers/writers
@@ -317,100 +1302,556 @@ impl Engine {
             .dependencies
             .clone();
 
-        for dep_call_id in &dependencies {
-            if !self.populate_metadata_for_call_id(*dep_call_id)? {
-                return Ok(false);
+        visited.path.push((call_id, identity));
+        let mut merged = Vec::with_capacity(dependencies.len());
+        for dep_call_id in dependencies {
+            match self.populate_metadata_for_call_id(dep_call_id, visited)? {
+                Some(dep_call_id) if !merged.contains(&dep_call_id) => merged.push(dep_call_id),
+                Some(_) => {}
+                None => return Ok(None),
             }
         }
+        visited.path.pop();
+
+        self.state
+            .read()
+            .metadata
+            .task_call_metadata_mut(call_id)
+            .unwrap()
+            .dependencies = merged;
+
+        Ok(Some(call_id))
+    }
 
-        Ok(true)
+    /// Create an error for a dependency cycle, given the task calls making up
+    /// the cycle (where the first and last are calls to the same task).
+    fn dependency_cycle_error(&self, cycle: &[TaskCallId]) -> errors::DependencyCycle {
+        let names = cycle
+            .iter()
+            .map(|&call_id| self.task_name(call_id))
+            .collect::<Vec<_>>();
+
+        // each task call after the first was made by a `depends` in the previous one
+        let metadata = self.metadata();
+        let spans = cycle
+            .iter()
+            .zip(&names)
+            .skip(1)
+            .zip(&names)
+            .map(|((&call_id, name), parent)| {
+                let span = metadata.get_task_call(call_id).unwrap().span;
+                LabeledSpan::new_with_span(Some(format!("`{parent}` depends on `{name}`")), span)
+            })
+            .collect();
+
+        errors::DependencyCycle {
+            cycle: names,
+            spans,
+        }
     }
 
-    fn spawn_task(&mut self, node: &RunNode) -> EngineResult<()> {
-        // try to abort this task and its transitive dependencies
-        self.abort_tree(node);
+    fn spawn_task(&mut self, call_id: TaskCallId) -> EngineResult<()> {
+        // abort any previous run of this task
+        self.abort_task(call_id);
 
         // lock handles early to prevent weirdness
         let mut handles = self.handles.lock();
 
         // use our own engine state
         let mut engine_state = self.engine_state.clone();
-        let mut stack = self.stack.clone();
+        let stack = self.stack.clone();
 
         // set up ctrlc handler so we can abort tasks individually
         let ctrlc = Arc::new(AtomicBool::default());
         engine_state.ctrlc = Some(ctrlc.clone());
+        let engine_state = Arc::new(engine_state);
+
+        let quiet = self.options.quiet;
+        let events = self.events;
+
+        let dirty_check = self.dirty_check();
+        let database = self.database.clone();
+        let key = self.call_key(call_id);
+
+        let generation = self.next_generation;
+        self.next_generation += 1;
 
-        let call_id = node.call_id;
+        let timeout = self.timeout(call_id);
 
         let state = self.state.clone();
+        let interrupted = ctrlc.clone();
+        let output_slot = Arc::new(Mutex::new(None));
+        let task_output = output_slot.clone();
 
+        // only wait on blocking work here (see `run_blocking`), so that the runtime is
+        // always free to handle timeouts and interrupts
         let abort_handle = self.task_pool.spawn(async move {
-            let (name, call_span) = {
-                let state = state.read();
+            let prepare = {
+                let (engine_state, state) = (engine_state.clone(), state.clone());
+                let (dirty_check, key) = (dirty_check.clone(), key.clone());
+                move || {
+                    let fingerprint = fingerprint_task_call(call_id, &engine_state);
+
+                    let state = state.read();
+                    let call = state.metadata.get_task_call(call_id).unwrap();
+                    let task = state.metadata.get_task(call.task_id).unwrap().clone();
+                    let dirtiness =
+                        dirty_check.check(&task.name.item, &call.metadata, &key, &fingerprint)?;
+
+                    Ok((fingerprint, dirtiness, task, call.span))
+                }
+            };
+            let (fingerprint, dirtiness, task, call_span) = run_blocking(prepare).await?;
+
+            let name = task.name.item.clone();
+            if !dirtiness.is_dirty() {
+                events.log_info("skipping task", &name);
+                events.emit(Event::TaskSkipped {
+                    call_id,
+                    task: name,
+                });
+                return Ok(TaskResult::new(call_id, generation, TaskStatus::Skipped));
+            }
 
-                let call = state.metadata.get_task_call(call_id).unwrap();
-                let call_span = call.span;
-                let name = state
-                    .metadata
-                    .get_task(call.task_id)
-                    .unwrap()
-                    .name
-                    .item
-                    .clone();
+            events.log_info("running task", &name);
+            events.emit(Event::TaskStarted {
+                call_id,
+                task: name.clone(),
+            });
+            let started_at = SystemTime::now();
+            let start_time = Instant::now();
+
+            let (retries, retry_delay) = (task.flags.retries, task.flags.retry_delay);
+            let mut attempt = 1;
+            let (status, error) = loop {
+                let run = {
+                    let (engine_state, state, name, task_output) = (
+                        engine_state.clone(),
+                        state.clone(),
+                        name.clone(),
+                        task_output.clone(),
+                    );
+                    // start each attempt from the same stack
+                    let mut stack = stack.clone();
+                    move || {
+                        // when quiet, capture the output of the task so that it can be shown on
+                        // failure
+                        let output = if quiet {
+                            let file = tempfile().map_err(|err| {
+                                EngineError::internal(format!(
+                                    "failed to create output file: {err}"
+                                ))
+                            })?;
+                            let file = Arc::new(file);
+                            *task_output.lock() = Some(file.clone());
+                            Some(file)
+                        } else {
+                            None
+                        };
+
+                        let result = match &output {
+                            Some(output) => eval_task_run_body_redirected(
+                                call_id,
+                                call_span,
+                                &engine_state,
+                                &mut stack,
+                                output.clone(),
+                            ),
+                            None => {
+                                eval_task_run_body(call_id, call_span, &engine_state, &mut stack)
+                            }
+                        };
+
+                        if let Some(output) = &output
+                            && !matches!(
+                                result,
+                                Ok(true) | Err(ShellError::InterruptedByUser { .. })
+                            )
+                        {
+                            replay_output(output, call_id, &name, events).map_err(|err| {
+                                EngineError::internal(format!("failed to show task output: {err}"))
+                            })?;
+                        }
+
+                        Ok(match result {
+                            // silently ignore intentional interrupt errors
+                            Err(ShellError::InterruptedByUser { .. }) => {
+                                (TaskStatus::Interrupted, None)
+                            }
+                            // quake internal errors stand in for errors recorded in the state, so
+                            // report those instead
+                            Err(err) if err.is_quake_internal() => {
+                                let errors = std::mem::take(&mut state.write().errors);
+                                for error in errors {
+                                    events.report_error_new(Some(call_id), &engine_state, &*error);
+                                }
+                                (TaskStatus::Failed, None)
+                            }
+                            // in human-readable mode, errors are reported together once the build
+                            // has finished
+                            Err(err) => {
+                                if events.is_json() {
+                                    events.report_error_new(Some(call_id), &engine_state, &err);
+                                }
+                                (TaskStatus::Failed, Some(err))
+                            }
+                            Ok(true) => (TaskStatus::Succeeded, None),
+                            Ok(false) => (TaskStatus::Failed, None),
+                        })
+                    }
+                };
+                let (status, error) = run_blocking(run).await?;
 
-                if !is_dirty(&call.metadata).map_err(|err| {
-                    EngineError::internal(format!("failed to check dirty status: {err}"))
-                })? {
-                    log_info!("skipping task", &name);
-                    return Ok((call_id, true));
+                // retry failed attempts, unless they were interrupted (externals stopped by
+                // Ctrl-C usually just fail)
+                if status != TaskStatus::Failed || attempt > retries {
+                    break (status, error);
+                }
+                if interrupted.load(Ordering::SeqCst) {
+                    break (TaskStatus::Interrupted, None);
                 }
 
-                (name, call_span)
+                attempt += 1;
+                let delay = retry_delay
+                    .map(|delay| delay.saturating_mul(2u32.saturating_pow(attempt - 2)))
+                    .unwrap_or_default();
+
+                events.log_info(
+                    "retrying task",
+                    &format!("{name} (attempt {attempt} of {})", retries + 1),
+                );
+                events.emit(Event::TaskRetrying {
+                    call_id,
+                    task: name.clone(),
+                    attempt,
+                    delay_ms: duration_ms(delay),
+                });
+                if sleep_unless_interrupted(delay, &interrupted).await {
+                    break (TaskStatus::Interrupted, None);
+                }
             };
+            let duration = start_time.elapsed();
 
-            log_info!("running task", &name);
-
-            let result = eval_task_run_body(call_id, call_span, &engine_state, &mut stack);
+            let outcome = match status {
+                TaskStatus::Succeeded => RunOutcome::Succeeded,
+                TaskStatus::Failed => RunOutcome::Failed,
+                TaskStatus::Interrupted => RunOutcome::Interrupted,
+                TaskStatus::Skipped | TaskStatus::TimedOut => unreachable!(),
+            };
+            let run = RunRecord {
+                started_at,
+                duration_ms: duration_ms(duration),
+                outcome,
+                fingerprint,
+            };
 
-            let success = match result {
-                // silently ignore intentional interrupt errors
-                Err(ShellError::InterruptedByUser { .. }) => return Ok((call_id, false)),
-                Err(err) => {
-                    // filter out quake internal errors--these will be emitted by quake itself
-                    if !err.is_quake_internal() {
-                        report_error_new(&engine_state, &err);
-                    }
+            // record the run, and the artifacts of the task if it succeeded (only hashing
+            // its files when content hashing is enabled)
+            run_blocking(move || {
+                let metadata = state
+                    .read()
+                    .metadata
+                    .task_call_metadata(call_id)
+                    .unwrap()
+                    .clone();
+                let files = if outcome == RunOutcome::Succeeded && dirty_check.content_hash {
+                    FileHashes::from_metadata(&dirty_check.project_root, &metadata).map(Some)
+                } else {
+                    Ok(None)
+                };
 
-                    false
+                if let Err(err) = files.and_then(|files| {
+                    let mut database = database.lock();
+                    database.record_run(key, run, metadata.artifacts, files);
+                    database.save()
+                }) {
+                    events.log_warning("failed to update build database", &err.to_string());
                 }
-                Ok(success) => success,
-            };
 
-            Ok((call_id, success))
+                Ok(())
+            })
+            .await?;
+
+            Ok(TaskResult {
+                error,
+                duration,
+                ..TaskResult::new(call_id, generation, status)
+            })
         });
 
         // insert the handle, dropping the lock
-        handles.insert(node.call_id, (abort_handle, ctrlc));
+        handles.insert(
+            call_id,
+            TaskHandle {
+                abort: abort_handle,
+                ctrlc,
+                generation,
+                start_time: Instant::now(),
+                timeout,
+                output: output_slot,
+            },
+        );
 
         Ok(())
     }
 
+    /// Find the running task call that will be first to exceed its timeout,
+    /// along with when it will do so.
+    fn next_timeout(&self) -> Option<(TaskCallId, Instant, Duration)> {
+        self.handles
+            .lock()
+            .iter()
+            .filter_map(|(&call_id, handle)| {
+                let timeout = handle.timeout?;
+                Some((call_id, handle.start_time, timeout))
+            })
+            // timeouts too long to represent will never be reached
+            .filter(|&(_, start_time, timeout)| start_time.checked_add(timeout).is_some())
+            .min_by_key(|&(_, start_time, timeout)| start_time + timeout)
+    }
+
+    /// Report that a task call has been stopped after exceeding its timeout.
+    ///
+    /// In human-readable mode, this is reported along with any other failures
+    /// once the build has finished.
+    fn report_timeout(&self, call_id: TaskCallId, elapsed: Duration) {
+        let task = self.task_name(call_id);
+
+        // show any output captured so far, as it likely shows why the task hung
+        let output = (self.handles.lock().get(&call_id)).and_then(|h| h.output.lock().clone());
+        if let Some(output) = output
+            && let Err(err) = replay_output(&output, call_id, &task, self.events)
+        {
+            self.events
+                .log_warning("failed to show task output", &err.to_string());
+        }
+
+        self.events.emit(Event::TaskFinished {
+            call_id,
+            task: task.clone(),
+            success: false,
+            duration_ms: duration_ms(elapsed),
+        });
+
+        if self.events.is_json() {
+            let error = errors::TaskCallFailure::TimedOut {
+                task,
+                span: self.metadata().get_task_call(call_id).unwrap().span,
+                elapsed,
+            };
+            self.events
+                .report_error_new(Some(call_id), &self.engine_state, &error);
+        }
+    }
+
+    /// Ask every running task call to stop, as the build has been interrupted
+    /// by the user, without aborting them so that any running externals can
+    /// exit first.
+    fn interrupt_all(&self) {
+        let running = {
+            let handles = self.handles.lock();
+            for handle in handles.values() {
+                handle.ctrlc.store(true, Ordering::SeqCst);
+            }
+            handles.keys().copied().collect::<Vec<_>>()
+        };
+
+        if !self.events.is_json() {
+            let tasks = running
+                .iter()
+                .map(|&call_id| self.task_name(call_id))
+                .collect::<Vec<_>>();
+            log_warning!(
+                "interrupted",
+                format!(
+                    "waiting for {} running task(s) to stop: {} (press Ctrl-C again to stop \
+                     immediately)",
+                    tasks.len(),
+                    tasks.join(", ")
+                )
+            );
+        }
+        self.events.emit(Event::BuildInterrupted { running });
+    }
+
     fn abort_all(&mut self) {
         let mut handles = self.handles.lock();
-        for (_, (abort, ctrlc)) in handles.drain() {
-            // set the ctrlc flag, will abort the task relatively quickly
-            ctrlc.store(true, Ordering::SeqCst);
-            abort.abort();
+        for (_, handle) in handles.drain() {
+            handle.abort();
+        }
+    }
+
+    fn abort_task(&mut self, call_id: TaskCallId) {
+        if let Some(handle) = self.handles.lock().remove(&call_id) {
+            handle.abort();
+        }
+    }
+}
+
+impl TaskHandle {
+    fn abort(&self) {
+        // set the ctrlc flag, will abort the task relatively quickly
+        self.ctrlc.store(true, Ordering::SeqCst);
+        self.abort.abort();
+    }
+}
+
+/// Run blocking work (such as evaluating nushell code or hashing files) on the
+/// blocking thread pool, so that it doesn't occupy the runtime's worker
+/// threads.
+///
+/// If the task waiting on this is aborted, the work will continue in the
+/// background until it finishes (e.g. once it has noticed its `ctrlc` flag).
+async fn run_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> EngineResult<T> + Send + 'static,
+) -> EngineResult<T> {
+    spawn_blocking(f)
+        .await
+        .map_err(|err| EngineError::internal(format!("failed to join blocking task: {err}")))?
+}
+
+/// Wait for a task call to exceed its timeout (see [`Engine::next_timeout`]),
+/// or forever if no running task calls have a timeout.
+///
+/// Returns the task call, and how long it has been running for.
+async fn wait_for_timeout(
+    next_timeout: Option<(TaskCallId, Instant, Duration)>,
+) -> (TaskCallId, Duration) {
+    match next_timeout {
+        Some((call_id, start_time, timeout)) => {
+            sleep_until((start_time + timeout).into()).await;
+            (call_id, start_time.elapsed())
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Sleep for `delay`, stopping early once `interrupted` is set.
+///
+/// Returns whether the sleep was interrupted.
+async fn sleep_unless_interrupted(delay: Duration, interrupted: &AtomicBool) -> bool {
+    let deadline = Instant::now().checked_add(delay);
+    while !interrupted.load(Ordering::SeqCst) {
+        let remaining = deadline.map_or(INTERRUPT_POLL_INTERVAL, |deadline| {
+            deadline.saturating_duration_since(Instant::now())
+        });
+        if remaining.is_zero() {
+            return false;
         }
+        sleep(remaining.min(INTERRUPT_POLL_INTERVAL)).await;
     }
+    true
+}
 
-    fn abort_tree(&mut self, root: &RunNode) {
-        if let Some((abort, ctrlc)) = self.handles.lock().get(&root.call_id) {
-            ctrlc.store(true, Ordering::SeqCst);
-            abort.abort();
+/// Forward interrupts from the user (e.g. Ctrl-C) to `sender`, until it is
+/// closed.
+///
+/// Unlike with [`tokio::signal::ctrl_c`], interrupts received while none are
+/// being waited for are not missed.
+async fn listen_for_interrupts(sender: UnboundedSender<()>) {
+    #[cfg(unix)]
+    let signals = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt());
+    #[cfg(windows)]
+    let signals = tokio::signal::windows::ctrl_c();
+
+    let Ok(mut signals) = signals else {
+        return;
+    };
+
+    while signals.recv().await.is_some() {
+        if sender.send(()).is_err() {
+            return;
         }
+    }
+}
 
-        root.children.iter().for_each(|c| self.abort_tree(c));
+/// Wait for the user to interrupt quake (see [`listen_for_interrupts`]), or
+/// forever if interrupts are not being listened for.
+async fn wait_for_interrupt(interrupts: &mut Option<UnboundedReceiver<()>>) {
+    if let Some(interrupts) = interrupts
+        && interrupts.recv().await.is_some()
+    {
+        return;
+    }
+
+    std::future::pending().await
+}
+
+/// Wait for changes to any watched files, or forever if there is no watcher.
+async fn wait_for_changes(watcher: &mut Option<&mut Watcher>) -> Vec<PathBuf> {
+    match watcher {
+        Some(watcher) => watcher.changed().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Write the captured output of a task call to stderr, or emit it as an event
+/// in JSON mode so that it does not break up the stream of events.
+fn replay_output(
+    mut output: &File,
+    call_id: TaskCallId,
+    task: &str,
+    events: EventEmitter,
+) -> io::Result<()> {
+    output.seek(SeekFrom::Start(0))?;
+
+    if events.is_json() {
+        let mut bytes = Vec::new();
+        output.read_to_end(&mut bytes)?;
+        events.emit(Event::TaskOutput {
+            call_id,
+            task: task.to_owned(),
+            output: String::from_utf8_lossy(&bytes).into_owned(),
+        });
+    } else {
+        io::copy(&mut output, &mut io::stderr())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_cycle() {
+        let mut visited = VisitedCalls::default();
+        assert_eq!(None, visited.find_cycle(0, &(0, "a".into())));
+
+        visited.path.push((0, (0, "a".into())));
+        visited.path.push((1, (1, "a".into())));
+        assert_eq!(None, visited.find_cycle(2, &(2, "a".into())));
+        // the same task with different arguments is not a cycle
+        assert_eq!(None, visited.find_cycle(2, &(1, "b".into())));
+        assert_eq!(Some(vec![0, 1, 2]), visited.find_cycle(2, &(0, "a".into())));
+        assert_eq!(Some(vec![1, 2]), visited.find_cycle(2, &(1, "a".into())));
+    }
+
+    #[test]
+    fn test_find_cycle_max_depth() {
+        let mut visited = VisitedCalls::default();
+        visited.path.push((0, (0, "".into())));
+        for depth in 1..MAX_DEPENDENCY_DEPTH {
+            visited.path.push((depth, (1, depth.to_string())));
+        }
+
+        let call_id = MAX_DEPENDENCY_DEPTH;
+        let cycle = visited.find_cycle(call_id, &(1, "next".into())).unwrap();
+        assert_eq!(vec![call_id - 1, call_id], cycle);
+
+        // otherwise, the whole path is reported
+        let cycle = visited.find_cycle(call_id, &(2, "".into())).unwrap();
+        assert_eq!(MAX_DEPENDENCY_DEPTH + 1, cycle.len());
+        assert_eq!(Some(&0), cycle.first());
+    }
+    #[test]
+    fn test_merge() {
+        let mut visited = VisitedCalls::default();
+        assert_eq!(None, visited.merge(0, (0, "a".into())));
+        assert_eq!(None, visited.merge(1, (0, "b".into())));
+        assert_eq!(None, visited.merge(2, (1, "a".into())));
+        assert_eq!(Some(0), visited.merge(3, (0, "a".into())));
+        assert_eq!(Some(2), visited.merge(4, (1, "a".into())));
     }
 }
@@ -1,4 +1,7 @@
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use nu_engine::CallExt;
 use nu_protocol::ast::Call;
@@ -8,6 +11,8 @@ use nu_protocol::{
 };
 use quake_core::metadata::{Task, TaskCallId, TaskFlags};
 
+use crate::nu::eval::eval_arguments;
+use crate::nu::utils::{expand_paths, parse_duration};
 use crate::state::State;
 
 use super::QUAKE_CATEGORY;
@@ -33,11 +38,34 @@ impl Command for DefTask {
                 "allow this task to be run concurrently with others",
                 Some('c'),
             )
+            .switch(
+                "no-dedup",
+                "run this task for every call, even if called with identical arguments",
+                None,
+            )
             .switch(
                 "pure",
                 "make this a purely declarative task, with only a single declaration body and no run body",
                 None,
             )
+            .named(
+                "timeout",
+                SyntaxShape::Duration,
+                "stop the task if it runs for longer than this",
+                Some('t'),
+            )
+            .named(
+                "retries",
+                SyntaxShape::Int,
+                "re-run the task up to this many times if it fails",
+                Some('r'),
+            )
+            .named(
+                "retry-delay",
+                SyntaxShape::Duration,
+                "wait before re-running the task, doubling the wait after each failure",
+                None,
+            )
             .required("params", SyntaxShape::Signature, "parameters")
             .required("first_body", SyntaxShape::Closure(None), "first body")
             .required("second_body", SyntaxShape::Closure(None), "second body")
@@ -78,6 +106,12 @@ impl Command for Subtask {
                 "allow this task to be run concurrently with others",
                 Some('c'),
             )
+            .named(
+                "timeout",
+                SyntaxShape::Duration,
+                "stop the task if it runs for longer than this",
+                Some('t'),
+            )
             .required("run_body", SyntaxShape::Closure(None), "run body")
             .category(Category::Custom(QUAKE_CATEGORY.to_owned()))
     }
@@ -97,6 +131,14 @@ impl Command for Subtask {
         );
         let flags = TaskFlags {
             concurrent: call.has_flag(engine_state, stack, "concurrent")?,
+            pure: false,
+            no_dedup: false,
+            timeout: call
+                .get_flag(engine_state, stack, "timeout")?
+                .map(|timeout| parse_duration(timeout, call.head))
+                .transpose()?,
+            retries: 0,
+            retry_delay: None,
         };
 
         let block = engine_state.get_block(closure.block_id);
@@ -143,6 +185,8 @@ impl Command for Subtask {
                 name.item.clone(),
                 Arc::new(Task {
                     name: name.clone(),
+                    description: None,
+                    depends: Vec::new(),
                     flags,
                     depends_decl_id: None,
                     decl_body: None,
@@ -237,18 +281,16 @@ impl Command for DependsTask {
         call: &Call,
         _input: PipelineData,
     ) -> Result<PipelineData, ShellError> {
+        // evaluate the arguments now, while any variables they refer to are in scope
+        let arguments = eval_arguments(&call.arguments, &self.signature, engine_state, stack)?;
+
         State::capture_errors_in_shell(engine_state, |state| {
             state.check_in_scope(stack, call.head)?;
 
             // register the call_id and add it as a dependency
             let call_id = state
                 .metadata
-                .register_task_call(
-                    self.task_id,
-                    call.span(),
-                    call.arguments.clone(),
-                    Vec::new(),
-                )
+                .register_task_call(self.task_id, call.span(), arguments, Vec::new())
                 .unwrap();
             state
                 .scope_metadata_mut(stack, call.head)?
@@ -275,8 +317,14 @@ impl Command for Sources {
             .input_output_types(vec![(Type::Nothing, Type::Nothing)])
             .required(
                 "files",
+                files_shape(),
+                "files, directories or glob patterns to be sourced",
+            )
+            .named(
+                "exclude",
                 SyntaxShape::List(Box::new(SyntaxShape::String)),
-                "files to be sourced",
+                "glob patterns of files to exclude",
+                Some('e'),
             )
             .category(Category::Custom(QUAKE_CATEGORY.to_owned()))
     }
@@ -292,13 +340,13 @@ impl Command for Sources {
         call: &Call,
         _input: PipelineData,
     ) -> Result<PipelineData, ShellError> {
-        let values: Vec<String> = call.req(engine_state, stack, 0)?;
+        let files = eval_files(engine_state, stack, call)?;
 
         State::capture_errors_in_shell(engine_state, |state| {
             state
                 .scope_metadata_mut(stack, call.head)?
                 .sources
-                .extend(values.iter().map(Into::into));
+                .extend(files);
             Ok(())
         })?;
 
@@ -319,8 +367,14 @@ impl Command for Produces {
             .input_output_types(vec![(Type::Nothing, Type::Nothing)])
             .required(
                 "files",
+                files_shape(),
+                "files, directories or glob patterns to be produced",
+            )
+            .named(
+                "exclude",
                 SyntaxShape::List(Box::new(SyntaxShape::String)),
-                "files to be produced",
+                "glob patterns of files to exclude",
+                Some('e'),
             )
             .category(Category::Custom(QUAKE_CATEGORY.to_owned()))
     }
@@ -336,16 +390,118 @@ impl Command for Produces {
         call: &Call,
         _input: PipelineData,
     ) -> Result<PipelineData, ShellError> {
-        let values: Vec<String> = call.req(engine_state, stack, 0)?;
+        let files = eval_files(engine_state, stack, call)?;
 
         State::capture_errors_in_shell(engine_state, |state| {
             state
                 .scope_metadata_mut(stack, call.head)?
                 .artifacts
-                .extend(values.iter().map(Into::into));
+                .extend(files);
             Ok(())
         })?;
 
         Ok(PipelineData::empty())
     }
 }
+
+#[derive(Clone)]
+pub struct DefaultJobs;
+
+impl Command for DefaultJobs {
+    fn name(&self) -> &str {
+        "default-jobs"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build("default-jobs")
+            .input_output_types(vec![(Type::Nothing, Type::Nothing)])
+            .required("jobs", SyntaxShape::Int, "maximum number of tasks")
+            .category(Category::Custom(QUAKE_CATEGORY.to_owned()))
+    }
+
+    fn usage(&self) -> &str {
+        "Set the maximum number of tasks to run at once, unless overridden"
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let jobs: Spanned<i64> = call.req(engine_state, stack, 0)?;
+        let value = usize::try_from(jobs.item)
+            .ok()
+            .and_then(NonZeroUsize::new)
+            .ok_or_else(|| ShellError::IncorrectValue {
+                msg: "number of jobs must be positive".to_owned(),
+                val_span: jobs.span,
+                call_span: call.head,
+            })?;
+
+        State::from_engine_state_mut(engine_state).jobs = Some(value);
+
+        Ok(PipelineData::empty())
+    }
+}
+
+#[derive(Clone)]
+pub struct DefaultTimeout;
+
+impl Command for DefaultTimeout {
+    fn name(&self) -> &str {
+        "default-timeout"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build("default-timeout")
+            .input_output_types(vec![(Type::Nothing, Type::Nothing)])
+            .required("timeout", SyntaxShape::Duration, "maximum task duration")
+            .category(Category::Custom(QUAKE_CATEGORY.to_owned()))
+    }
+
+    fn usage(&self) -> &str {
+        "Set the maximum amount of time any task may run for, unless overridden"
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let timeout: Duration = parse_duration(call.req(engine_state, stack, 0)?, call.head)?;
+
+        State::from_engine_state_mut(engine_state).timeout = Some(timeout);
+
+        Ok(PipelineData::empty())
+    }
+}
+
+/// The shape of the files accepted by [`Sources`] and [`Produces`].
+fn files_shape() -> SyntaxShape {
+    SyntaxShape::List(Box::new(SyntaxShape::OneOf(vec![
+        SyntaxShape::String,
+        SyntaxShape::GlobPattern,
+    ])))
+}
+
+/// Evaluate and expand the files passed to [`Sources`] or [`Produces`] (see
+/// [`expand_paths`]).
+fn eval_files(
+    engine_state: &EngineState,
+    stack: &mut Stack,
+    call: &Call,
+) -> Result<Vec<PathBuf>, ShellError> {
+    let values: Vec<Value> = call.req(engine_state, stack, 0)?;
+    let exclude: Vec<Spanned<String>> = call
+        .get_flag(engine_state, stack, "exclude")?
+        .unwrap_or_default();
+
+    let project_root = State::from_engine_state(engine_state)
+        .project_root()
+        .to_owned();
+    expand_paths(&project_root, &values, &exclude)
+}
@@ -1,9 +1,13 @@
+use std::fs::File;
+use std::sync::Arc;
+
 use nu_protocol::ast::{Argument, Block};
 use nu_protocol::debugger::WithoutDebug;
-use nu_protocol::engine::{EngineState, Stack};
-use nu_protocol::{PipelineData, Span, Value, VarId};
+use nu_protocol::engine::{EngineState, Redirection, Stack};
+use nu_protocol::{Config, IoStream, PipelineData, Signature, Span, Value, VarId};
 
-use quake_core::metadata::TaskCallId;
+use quake_core::database::{ContentHash, Fingerprint};
+use quake_core::metadata::{TaskArgument, TaskCallId};
 use quake_core::prelude::*;
 
 use crate::nu::utils::set_last_exit_code;
@@ -33,7 +37,12 @@ pub fn eval_block(
 
     match result {
         Ok(pipeline_data) => {
-            let exit_code = pipeline_data.print(engine_state, stack, false, false)?;
+            // don't print the result if output is being redirected (e.g. with `--quiet`)
+            let exit_code = if matches!(stack.stdout(), IoStream::File(_)) {
+                pipeline_data.drain_with_exit_code()?
+            } else {
+                pipeline_data.print(engine_state, stack, false, false)?
+            };
             set_last_exit_code(stack, exit_code);
             if exit_code != 0 {
                 return Ok(false);
@@ -121,11 +130,188 @@ pub fn eval_task_run_body(
     )
 }
 
+/// Evaluate a task's run body (see [`eval_task_run_body`]), redirecting both
+/// stdout and stderr of any executed commands to `output`.
+pub fn eval_task_run_body_redirected(
+    call_id: TaskCallId,
+    span: Span,
+    engine_state: &EngineState,
+    stack: &mut Stack,
+    output: Arc<File>,
+) -> ShellResult<bool> {
+    let mut stack = stack.push_redirection(
+        Some(Redirection::File(output.clone())),
+        Some(Redirection::File(output)),
+    );
+    eval_task_run_body(call_id, span, engine_state, &mut stack)
+}
+
+/// Evaluate the arguments of a call to a task with the given signature, so
+/// that they can be passed to the task call once it is run.
+///
+/// Flags are identified by their long names, and any lists spread into the
+/// rest parameter are flattened into positional arguments.
+pub fn eval_arguments(
+    arguments: &[Argument],
+    signature: &Signature,
+    engine_state: &EngineState,
+    stack: &mut Stack,
+) -> ShellResult<Vec<TaskArgument>> {
+    let mut values = Vec::with_capacity(arguments.len());
+
+    for arg in arguments {
+        match arg {
+            Argument::Positional(expr) | Argument::Unknown(expr) => {
+                values.push(TaskArgument::Positional(nu_engine::eval_expression::<
+                    WithoutDebug,
+                >(
+                    engine_state, stack, expr
+                )?))
+            }
+            Argument::Named((long, short, expr)) => {
+                // short flags are parsed without their long names
+                let long = match short.as_ref().and_then(|s| s.item.chars().next()) {
+                    Some(short) if long.item.is_empty() => {
+                        signature.get_long_flag(short).unwrap_or_default()
+                    }
+                    _ => long.item.clone(),
+                };
+                let value = expr
+                    .as_ref()
+                    .map(|expr| {
+                        nu_engine::eval_expression::<WithoutDebug>(engine_state, stack, expr)
+                    })
+                    .transpose()?;
+                values.push(TaskArgument::Named(long, value));
+            }
+            Argument::Spread(expr) => {
+                match nu_engine::eval_expression::<WithoutDebug>(engine_state, stack, expr)? {
+                    Value::List { vals, .. } => {
+                        values.extend(vals.into_iter().map(TaskArgument::Positional))
+                    }
+                    _ => return Err(ShellError::CannotSpreadAsList { span: expr.span }),
+                }
+            }
+        }
+    }
+
+    Ok(values)
+}
+
+/// Compute the [`Fingerprint`] of a task call from its arguments, its
+/// constants, and the source text of the bodies of its task.
+pub fn fingerprint_task_call(call_id: TaskCallId, engine_state: &EngineState) -> ContentHash {
+    let (call, task) = {
+        let state = State::from_engine_state(engine_state);
+        let call = state.metadata.get_task_call(call_id).unwrap().clone();
+        let task = state.metadata.get_task(call.task_id).unwrap().clone();
+        (call, task)
+    };
+
+    let config = engine_state.get_config();
+    let mut fingerprint = Fingerprint::new();
+
+    for block_id in [task.decl_body, task.run_body] {
+        match block_id.and_then(|id| engine_state.get_block(id).span) {
+            Some(span) => fingerprint.add(engine_state.get_span_contents(span)),
+            None => fingerprint.add(b""),
+        };
+    }
+
+    for arg in &call.arguments {
+        let (kind, value) = match arg {
+            TaskArgument::Positional(value) => ("positional".to_owned(), Some(value)),
+            TaskArgument::Named(long, value) => (format!("--{long}"), value.as_ref()),
+        };
+
+        fingerprint.add(kind);
+        if let Some(value) = value {
+            fingerprint.add(value_repr(value, config));
+        }
+    }
+
+    // variable IDs may differ between invocations, so only the values are used
+    for (_, value) in &call.constants {
+        fingerprint.add(value_repr(value, config));
+    }
+
+    fingerprint.finish()
+}
+
+/// Describe a task call as the name of its task followed by its arguments and
+/// constants, e.g. `build --release --target="x86_64"`.
+///
+/// Unlike the source text of the task call, this differs between task calls
+/// with different arguments, so it identifies the task call across
+/// invocations.
+pub fn describe_task_call(call_id: TaskCallId, engine_state: &EngineState) -> String {
+    let state = State::from_engine_state(engine_state);
+    let call = state.metadata.get_task_call(call_id).unwrap();
+
+    let config = engine_state.get_config();
+    let mut description = state
+        .metadata
+        .get_task(call.task_id)
+        .unwrap()
+        .name
+        .item
+        .clone();
+
+    for arg in &call.arguments {
+        description.push(' ');
+        match arg {
+            TaskArgument::Positional(value) => description.push_str(&value_label(value, config)),
+            TaskArgument::Named(long, value) => {
+                description.push_str("--");
+                description.push_str(long);
+                if let Some(value) = value {
+                    description.push('=');
+                    description.push_str(&value_label(value, config));
+                }
+            }
+        }
+    }
+
+    for (_, value) in &call.constants {
+        description.push(' ');
+        description.push_str(&value_label(value, config));
+    }
+
+    description
+}
+
+/// A representation of a value that is independent of its span.
+fn value_repr(value: &Value, config: &Config) -> String {
+    format!(
+        "{ty}: {value}",
+        ty = value.get_type(),
+        value = value.to_expanded_string(", ", config)
+    )
+}
+
+/// A readable representation of a value, in which strings are quoted so that
+/// they can be told apart from other values.
+fn value_label(value: &Value, config: &Config) -> String {
+    match value {
+        Value::String { val, .. } => {
+            format!("\"{}\"", val.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+        Value::List { vals, .. } => {
+            let vals = vals
+                .iter()
+                .map(|v| value_label(v, config))
+                .collect::<Vec<_>>();
+            format!("[{}]", vals.join(", "))
+        }
+        value => value.to_expanded_string(", ", config),
+    }
+}
+
 /// Similar to [`eval_call`](nu_engine::eval_call), but with manual blocks and
-/// arguments.
+/// evaluated arguments.
 fn eval_body(
     block: &Block,
-    arguments: &[Argument],
+    arguments: &[TaskArgument],
     constants: &[(VarId, Value)],
     span: Span,
     engine_state: &EngineState,
@@ -135,16 +321,21 @@ fn eval_body(
 
     let mut callee_stack = stack.gather_captures(engine_state, &block.captures);
 
+    let positional_count =
+        signature.required_positional.len() + signature.optional_positional.len();
+
     let mut positional_arg_vals = Vec::with_capacity(arguments.len());
     let mut named_arg_vals = Vec::with_capacity(arguments.len());
-    let mut rest_arg_val = None;
+    // positional arguments past those of the signature
+    let mut rest_arg_vals = Vec::new();
 
     for arg in arguments {
         match arg {
-            Argument::Positional(a) => positional_arg_vals.push(a),
-            Argument::Named(a) => named_arg_vals.push(a),
-            Argument::Spread(rest) => rest_arg_val = Some(rest),
-            Argument::Unknown(_) => unimplemented!("Argument::Unknown in task call"),
+            TaskArgument::Positional(value) if positional_arg_vals.len() < positional_count => {
+                positional_arg_vals.push(value)
+            }
+            TaskArgument::Positional(value) => rest_arg_vals.push(value.clone()),
+            TaskArgument::Named(long, value) => named_arg_vals.push((long, value)),
         }
     }
 
@@ -154,8 +345,8 @@ fn eval_body(
         .chain(signature.optional_positional.iter())
         .enumerate()
     {
-        let value = if let Some(expr) = positional_arg_vals.get(param_idx) {
-            nu_engine::eval_expression::<WithoutDebug>(engine_state, stack, expr)?
+        let value = if let Some(&value) = positional_arg_vals.get(param_idx) {
+            value.clone()
         } else if let Some(value) = &param.default_value {
             value.clone()
         } else {
@@ -165,27 +356,23 @@ fn eval_body(
         callee_stack.add_var(param.var_id.unwrap(), value);
     }
 
-    if let (Some(rest_arg), Some(rest_val)) = (&signature.rest_positional, &rest_arg_val) {
-        callee_stack.add_var(
-            rest_arg.var_id.unwrap(),
-            nu_engine::eval_expression::<WithoutDebug>(engine_state, stack, rest_val)?,
-        );
+    // the rest parameter is always bound, to an empty list if there are no extra
+    // arguments
+    if let Some(rest_arg) = &signature.rest_positional {
+        let span = rest_arg_vals.first().map_or(span, |v| v.span());
+        callee_stack.add_var(rest_arg.var_id.unwrap(), Value::list(rest_arg_vals, span));
     }
 
     for named in &signature.named {
         let var_id = named.var_id.unwrap();
 
-        let value = if let Some(expr) = named_arg_vals
+        let value = if let Some(value) = named_arg_vals
             .iter()
-            .find(|(long, short, _)| {
-                named.long == long.item
-                    || (named.short.is_some()
-                        && named.short == short.as_ref().and_then(|s| s.item.chars().next()))
-            })
-            .map(|(_, _, expr)| expr)
+            .find(|(long, _)| named.long == **long)
+            .map(|(_, value)| value)
         {
-            if let Some(expr) = expr {
-                nu_engine::eval_expression::<WithoutDebug>(engine_state, stack, expr)?
+            if let Some(value) = value {
+                value.clone()
             } else if let Some(value) = &named.default_value {
                 value.clone()
             } else {
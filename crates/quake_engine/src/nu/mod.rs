@@ -82,7 +82,9 @@ pub fn create_engine_state(state: Arc<RwLock<State>>) -> EngineState {
             Subtask,
             Depends,
             Sources,
-            Produces
+            Produces,
+            DefaultJobs,
+            DefaultTimeout
         };
 
         working_set.render()
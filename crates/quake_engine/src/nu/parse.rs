@@ -7,12 +7,13 @@ use nu_protocol::ast::{
     Argument, Block, Call, Expr, Expression, ExternalArgument, MatchPattern, Pattern, RecordItem,
 };
 use nu_protocol::engine::StateWorkingSet;
-use nu_protocol::{span, Category, DeclId, Spanned, Type};
+use nu_protocol::{span, Category, DeclId, Span, Spanned, Type};
 
 use quake_core::metadata::{Task, TaskFlags};
 use quake_core::prelude::*;
 
 use crate::nu::commands::DependsTask;
+use crate::nu::utils::parse_duration;
 use crate::state::State;
 
 use super::{QUAKE_CATEGORY, QUAKE_SCOPE_VARIABLE_ID};
@@ -38,8 +39,30 @@ fn parse_def_task(
     // try to extract flags--must be const eval
     let flags = TaskFlags {
         concurrent: call.has_flag_const(working_set, "concurrent")?,
+        pure: call.has_flag_const(working_set, "pure")?,
+        no_dedup: call.has_flag_const(working_set, "no-dedup")?,
+        timeout: call
+            .get_flag_const(working_set, "timeout")?
+            .map(|timeout| parse_duration(timeout, call.head))
+            .transpose()?,
+        retries: call
+            .get_flag_const::<Spanned<i64>>(working_set, "retries")?
+            .map(|retries| {
+                u32::try_from(retries.item).map_err(|_| ShellError::IncorrectValue {
+                    msg: "number of retries must not be negative".to_owned(),
+                    val_span: retries.span,
+                    call_span: call.head,
+                })
+            })
+            .transpose()?
+            .unwrap_or_default(),
+        retry_delay: call
+            .get_flag_const(working_set, "retry-delay")?
+            .map(|delay| parse_duration(delay, call.head))
+            .transpose()?,
     };
-    let is_pure = call.has_flag_const(working_set, "pure")?;
+    let is_pure = flags.pure;
+    let description = doc_comment(working_set, call.head);
 
     // extract and update signature in place
     let Some(Expression {
@@ -122,6 +145,7 @@ fn parse_def_task(
         decl_id
     };
 
+    let mut depends = Vec::new();
     if let Some(decl_body) = decl_body {
         // add the task call scope ID variable to the block's captures
         let mut block = working_set.get_block_mut(decl_body).clone();
@@ -129,7 +153,12 @@ fn parse_def_task(
 
         // transform `Depends` calls to `DependsTask`
         modify_calls(working_set, b"depends", &mut block, |working_set, call| {
-            state.capture_errors(|state| transform_depends(call, working_set, state));
+            if let Some(Some(dep_name)) =
+                state.capture_errors(|state| transform_depends(call, working_set, state))
+                && !depends.contains(&dep_name)
+            {
+                depends.push(dep_name);
+            }
         });
 
         *working_set.get_block_mut(decl_body) = block;
@@ -150,6 +179,8 @@ fn parse_def_task(
         name.item.clone(),
         Arc::new(Task {
             name,
+            description,
+            depends,
             flags,
             depends_decl_id: Some(depends_decl_id),
             decl_body,
@@ -169,25 +200,59 @@ fn parse_def_task(
     Ok(())
 }
 
+/// Get the doc comment directly above a call (i.e. the consecutive lines of
+/// comments immediately preceding it), as nushell does for `def`.
+fn doc_comment(working_set: &StateWorkingSet<'_>, span: Span) -> Option<String> {
+    let &(_, file_start, _) = working_set
+        .files()
+        .find(|(_, start, end)| (*start..*end).contains(&span.start))?;
+    let source = working_set.get_span_contents(Span::new(file_start, span.start));
+    comment_above(&String::from_utf8_lossy(source))
+}
+
+/// Get the comment directly above a call, given the source text preceding it.
+fn comment_above(source: &str) -> Option<String> {
+    // skip the line of the call itself, which may be the first line of the file
+    let (source, _) = source.rsplit_once('\n')?;
+
+    // unlike `lines`, a trailing blank line separates the comment from the call
+    let mut comments = source
+        .split('\n')
+        .rev()
+        .map_while(|line| line.trim().strip_prefix('#'))
+        .map(|line| line.strip_prefix(' ').unwrap_or(line))
+        .collect::<Vec<_>>();
+    comments.reverse();
+
+    let description = comments.join("\n");
+    (!description.trim().is_empty()).then(|| description.trim().to_owned())
+}
+
+/// Transform a `depends` call into a call to the `DependsTask` command of the
+/// task it depends upon.
+///
+/// Returns the name of the task, or `None` if it could not be determined.
 fn transform_depends(
     call: &mut Box<Call>,
     working_set: &mut StateWorkingSet<'_>,
     state: &mut State,
-) -> DiagResult<()> {
+) -> DiagResult<Option<String>> {
     // update the decl id to the corresponding `DependsTask` command
     // extract dep name--must be const eval
     let Ok(dep_id) = call.req_const::<Spanned<String>>(working_set, 0) else {
-        return Ok(());
+        return Ok(None);
     };
+    let dep_name = dep_id.item.clone();
 
     // find the decl ID to the corresponding `DependsTask` command
     let depends_decl_id = state
         .metadata
         .find_task(&dep_id.item, Some(dep_id.span))?
         .depends_decl_id
-        .ok_or(errors::TaskNotFound {
-            name: dep_id.item,
-            span: Some(dep_id.span),
+        .ok_or_else(|| {
+            state
+                .metadata
+                .task_not_found(&dep_id.item, Some(dep_id.span))
         })?;
 
     *call = {
@@ -239,7 +304,7 @@ fn transform_depends(
         call
     };
 
-    Ok(())
+    Ok(Some(dep_name))
 }
 
 fn modify_calls(
@@ -398,3 +463,29 @@ fn modify_calls_in_pattern(
         | Pattern::Garbage => {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::comment_above;
+
+    #[test]
+    fn test_comment_above() {
+        assert_eq!(None, comment_above(""));
+        assert_eq!(None, comment_above("    "));
+        assert_eq!(None, comment_above("# not a doc comment\n\n"));
+        assert_eq!(
+            Some("Build the project.".to_owned()),
+            comment_above("# Build the project.\n")
+        );
+        assert_eq!(
+            Some("Build the project.\n\nIn release mode.".to_owned()),
+            comment_above(
+                "def-task clean {}\n\n  # Build the project.\n  #\n  # In release mode.\n  "
+            )
+        );
+        assert_eq!(
+            Some("Run the tests.".to_owned()),
+            comment_above("# Build the project.\ndef-task build {}\n# Run the tests.\n")
+        );
+    }
+}
@@ -1,5 +1,11 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use nu_glob::{MatchOptions, Pattern};
 use nu_protocol::engine::Stack;
-use nu_protocol::{Span, Value};
+use nu_protocol::{ShellError, Span, Spanned, Value};
+use walkdir::WalkDir;
 
 pub fn set_last_exit_code(stack: &mut Stack, exit_code: i64) {
     stack.add_env_var(
@@ -7,3 +13,187 @@ pub fn set_last_exit_code(stack: &mut Stack, exit_code: i64) {
         Value::int(exit_code, Span::unknown()),
     );
 }
+
+/// Convert a duration value (in nanoseconds) passed to a command into a
+/// [`Duration`], which must be positive.
+pub fn parse_duration(duration: Spanned<i64>, call_span: Span) -> Result<Duration, ShellError> {
+    u64::try_from(duration.item)
+        .ok()
+        .filter(|nanos| *nanos > 0)
+        .map(Duration::from_nanos)
+        .ok_or_else(|| ShellError::IncorrectValue {
+            msg: "duration must be positive".to_owned(),
+            val_span: duration.span,
+            call_span,
+        })
+}
+
+/// Expand a list of paths relative to `base` into a list of files.
+///
+/// Each value may be a string or a glob value. Glob patterns are expanded to
+/// the files matching them (unless given as a glob value that disables
+/// expansion), and directories are expanded recursively to the files inside of
+/// them. Any other paths are kept as-is, regardless of whether or not they
+/// exist. Files matching any of the `exclude` patterns are then removed.
+///
+/// Paths inside of `base` are returned relative to it.
+pub fn expand_paths(
+    base: &Path,
+    values: &[Value],
+    exclude: &[Spanned<String>],
+) -> Result<Vec<PathBuf>, ShellError> {
+    let exclude = exclude
+        .iter()
+        .map(|pattern| compile_pattern(&pattern.item, pattern.span))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut seen = HashSet::new();
+    let mut paths = Vec::new();
+    let mut push = |path: PathBuf| {
+        let path = path.strip_prefix(base).map(Path::to_owned).unwrap_or(path);
+        if !exclude.iter().any(|p| p.matches_path(&path)) && seen.insert(path.clone()) {
+            paths.push(path);
+        }
+    };
+
+    for value in values {
+        let span = value.span();
+        let (path, expand) = match value {
+            Value::String { val, .. } => (val, true),
+            Value::Glob { val, no_expand, .. } => (val, !no_expand),
+            _ => {
+                return Err(ShellError::CantConvert {
+                    to_type: "path".to_owned(),
+                    from_type: value.get_type().to_string(),
+                    span,
+                    help: None,
+                });
+            }
+        };
+
+        if expand && nu_glob::is_glob(path) {
+            // escape the base directory, as it may contain special characters itself
+            let pattern = Path::new(&Pattern::escape(&base.to_string_lossy())).join(path);
+            let matches =
+                nu_glob::glob_with(&pattern.to_string_lossy(), MatchOptions::default())
+                    .map_err(|err| glob_error("invalid glob pattern", err.msg.to_owned(), span))?;
+
+            for entry in matches {
+                let entry = entry.map_err(|err| {
+                    glob_error("failed to expand glob pattern", err.to_string(), span)
+                })?;
+                if entry.is_dir() {
+                    walk_dir(&entry, &mut push);
+                } else {
+                    push(entry);
+                }
+            }
+            continue;
+        }
+
+        let path = base.join(path);
+        if path.is_dir() {
+            walk_dir(&path, &mut push);
+        } else {
+            push(path);
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Recursively visit all files inside of a directory.
+fn walk_dir(dir: &Path, f: &mut impl FnMut(PathBuf)) {
+    WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .for_each(|entry| f(entry.into_path()));
+}
+
+fn compile_pattern(pattern: &str, span: Span) -> Result<Pattern, ShellError> {
+    Pattern::new(pattern)
+        .map_err(|err| glob_error("invalid glob pattern", err.msg.to_owned(), span))
+}
+
+fn glob_error(error: &str, msg: String, span: Span) -> ShellError {
+    ShellError::GenericError {
+        error: error.to_owned(),
+        msg,
+        span: Some(span),
+        help: None,
+        inner: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn expand(base: &Path, values: &[Value], exclude: &[&str]) -> Vec<PathBuf> {
+        let exclude = exclude
+            .iter()
+            .map(|pattern| Spanned {
+                item: (*pattern).to_owned(),
+                span: Span::unknown(),
+            })
+            .collect::<Vec<_>>();
+        expand_paths(base, values, &exclude).unwrap()
+    }
+
+    fn string(val: &str) -> Value {
+        Value::string(val, Span::unknown())
+    }
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn test_expand_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path();
+        fs::create_dir_all(base.join("src/gen")).unwrap();
+        for file in ["a.txt", "b.rs", "src/lib.rs", "src/main.rs", "src/gen/x.rs"] {
+            fs::write(base.join(file), "").unwrap();
+        }
+
+        // paths are relative to the base, whether or not they exist
+        assert_eq!(
+            paths(&["a.txt", "missing.txt"]),
+            expand(base, &[string("a.txt"), string("missing.txt")], &[])
+        );
+
+        // directories are expanded into the files inside of them
+        assert_eq!(
+            paths(&["src/gen/x.rs", "src/lib.rs", "src/main.rs"]),
+            expand(base, &[string("src")], &[])
+        );
+
+        assert_eq!(paths(&["b.rs"]), expand(base, &[string("*.rs")], &[]));
+        assert_eq!(
+            paths(&["src/lib.rs", "src/main.rs"]),
+            expand(base, &[string("src/*.rs")], &[])
+        );
+        assert_eq!(
+            paths(&["*.rs"]),
+            expand(base, &[Value::glob("*.rs", true, Span::unknown())], &[])
+        );
+
+        // duplicates are only included once
+        assert_eq!(
+            paths(&["a.txt", "b.rs"]),
+            expand(base, &[string("a.txt"), string("*.*"), string("b.rs")], &[])
+        );
+
+        assert_eq!(
+            paths(&["src/lib.rs", "src/main.rs"]),
+            expand(base, &[string("src")], &["src/gen/*"])
+        );
+
+        assert!(expand_paths(base, &[Value::int(1, Span::unknown())], &[]).is_err());
+    }
+}
@@ -0,0 +1,46 @@
+//! Plans of the task calls a build would run, as shown by a dry run (see
+//! [`Engine::plan`](crate::Engine::plan)).
+
+use serde::Serialize;
+
+use quake_core::metadata::TaskCallId;
+use quake_core::utils::Dirtiness;
+
+/// The task calls a build would run (or skip), in order of execution.
+#[derive(Debug, Clone, Serialize)]
+pub struct Plan {
+    pub calls: Vec<PlannedCall>,
+}
+
+impl Plan {
+    /// Iterate over the task calls in each stage of the build (see
+    /// [`PlannedCall::stage`]).
+    pub fn stages(&self) -> impl Iterator<Item = &[PlannedCall]> {
+        self.calls.chunk_by(|a, b| a.stage == b.stage)
+    }
+}
+
+/// A task call in a [`Plan`].
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedCall {
+    pub call_id: TaskCallId,
+    pub task: String,
+    /// The task along with its arguments, e.g. `build --release`.
+    pub call: String,
+    /// The stage of the build the task call would start in, starting from 0.
+    ///
+    /// Each stage starts once all task calls in the previous stage have
+    /// finished, so task calls in the same stage could run concurrently.
+    pub stage: usize,
+    pub concurrent: bool,
+    /// The task calls that must finish before this one starts.
+    pub dependencies: Vec<TaskCallId>,
+    /// Whether the task call would be run, rather than skipped.
+    ///
+    /// Note that this is determined from the current state of its files, so a
+    /// task call that would be skipped may still run once the task calls it
+    /// depends on have run.
+    pub run: bool,
+    #[serde(flatten)]
+    pub dirtiness: Dirtiness,
+}
@@ -0,0 +1,338 @@
+use std::collections::{HashMap, HashSet};
+
+use quake_core::metadata::{Metadata, TaskCallId};
+
+/// The graph of task calls that must be run in order to run one or more task
+/// calls, where each task call may only be started once all of its
+/// dependencies have finished.
+///
+/// A task call depended upon by multiple other task calls appears in the graph
+/// only once, and so is only run once.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunGraph {
+    roots: Vec<TaskCallId>,
+    order: Vec<TaskCallId>,
+    dependencies: HashMap<TaskCallId, Vec<TaskCallId>>,
+    dependents: HashMap<TaskCallId, Vec<TaskCallId>>,
+}
+
+impl RunGraph {
+    /// Generate the run graph for the given task calls from the dependencies
+    /// recorded in the metadata of them and their transitive dependencies.
+    ///
+    /// The task calls are ordered as given where possible, so that each one
+    /// comes after every task call given before it that is not one of its
+    /// dependents.
+    pub fn new(roots: &[TaskCallId], metadata: &Metadata) -> Self {
+        let mut graph = Self {
+            roots: roots.to_vec(),
+            ..Default::default()
+        };
+        for &call_id in roots {
+            graph.visit(call_id, metadata);
+        }
+        graph
+    }
+
+    fn visit(&mut self, call_id: TaskCallId, metadata: &Metadata) {
+        if self.dependencies.contains_key(&call_id) {
+            return;
+        }
+
+        // keep the order the dependencies were declared in
+        let mut dependencies = Vec::new();
+        for &dep in &metadata.task_call_metadata(call_id).unwrap().dependencies {
+            if !dependencies.contains(&dep) {
+                dependencies.push(dep);
+            }
+        }
+
+        self.dependencies.insert(call_id, dependencies.clone());
+        self.dependents.entry(call_id).or_default();
+
+        for dep in dependencies {
+            self.visit(dep, metadata);
+            self.dependents.entry(dep).or_default().push(call_id);
+        }
+
+        // only add the node once all of its dependencies have been added
+        self.order.push(call_id);
+    }
+
+    /// The task calls the graph was generated for.
+    pub fn roots(&self) -> &[TaskCallId] {
+        &self.roots
+    }
+
+    /// All task calls in the graph, in an order of execution such that each
+    /// task call comes after all of its dependencies.
+    pub fn order(&self) -> &[TaskCallId] {
+        &self.order
+    }
+
+    pub fn contains(&self, call_id: TaskCallId) -> bool {
+        self.dependencies.contains_key(&call_id)
+    }
+
+    /// The direct dependencies of a task call.
+    pub fn dependencies(&self, call_id: TaskCallId) -> &[TaskCallId] {
+        self.dependencies
+            .get(&call_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The task calls that directly depend on a task call.
+    pub fn dependents(&self, call_id: TaskCallId) -> &[TaskCallId] {
+        self.dependents
+            .get(&call_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Select the pending task calls that may be started, in order of
+    /// execution, given the task calls that are running and those that have
+    /// finished.
+    ///
+    /// No more than `jobs` task calls are run at once. Task calls that are not
+    /// concurrent (according to `is_concurrent`) are only started once no other
+    /// task calls are running, and no further task calls are started until
+    /// they have finished.
+    pub fn ready(
+        &self,
+        pending: &HashSet<TaskCallId>,
+        finished: &HashSet<TaskCallId>,
+        running: &[TaskCallId],
+        jobs: usize,
+        is_concurrent: impl Fn(TaskCallId) -> bool,
+    ) -> Vec<TaskCallId> {
+        let mut running = running.to_vec();
+        let mut ready = Vec::new();
+
+        for &call_id in &self.order {
+            if !pending.contains(&call_id) {
+                continue;
+            }
+
+            if running.len() >= jobs || running.iter().any(|&c| !is_concurrent(c)) {
+                break;
+            }
+
+            if !self
+                .dependencies(call_id)
+                .iter()
+                .all(|dep| finished.contains(dep))
+            {
+                continue;
+            }
+
+            // wait for all running tasks to finish first
+            if !is_concurrent(call_id) && !running.is_empty() {
+                break;
+            }
+
+            running.push(call_id);
+            ready.push(call_id);
+        }
+
+        ready
+    }
+
+    /// Group the task calls into the stages they would be run in (see
+    /// [`RunGraph::ready`]), if every task call were to take the same amount
+    /// of time, such that each stage starts once the previous stage has
+    /// finished.
+    pub fn stages(
+        &self,
+        jobs: usize,
+        is_concurrent: impl Fn(TaskCallId) -> bool,
+    ) -> Vec<Vec<TaskCallId>> {
+        let mut pending: HashSet<TaskCallId> = self.order.iter().copied().collect();
+        let mut finished = HashSet::new();
+        let mut stages = Vec::new();
+
+        while !pending.is_empty() {
+            let stage = self.ready(&pending, &finished, &[], jobs, &is_concurrent);
+            if stage.is_empty() {
+                break;
+            }
+
+            for call_id in &stage {
+                pending.remove(call_id);
+                finished.insert(*call_id);
+            }
+            stages.push(stage);
+        }
+
+        stages
+    }
+
+    /// Collect a task call along with all of its transitive dependencies.
+    pub fn with_dependencies(&self, call_id: TaskCallId) -> HashSet<TaskCallId> {
+        self.collect(call_id, |c| self.dependencies(c))
+    }
+
+    /// Collect a task call along with all task calls that transitively depend
+    /// on it.
+    pub fn with_dependents(&self, call_id: TaskCallId) -> HashSet<TaskCallId> {
+        self.collect(call_id, |c| self.dependents(c))
+    }
+
+    fn collect<'a>(
+        &'a self,
+        call_id: TaskCallId,
+        edges: impl Fn(TaskCallId) -> &'a [TaskCallId],
+    ) -> HashSet<TaskCallId> {
+        let mut collected = HashSet::new();
+        let mut stack = vec![call_id];
+
+        while let Some(call_id) = stack.pop() {
+            if collected.insert(call_id) {
+                stack.extend_from_slice(edges(call_id));
+            }
+        }
+
+        collected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nu_protocol::{Span, Spanned};
+    use quake_core::metadata::{Task, TaskFlags};
+
+    use super::*;
+
+    /// Create metadata with a task call for each list of dependencies, where
+    /// the IDs of the task calls are their indices.
+    fn metadata(dependencies: &[&[TaskCallId]]) -> Metadata {
+        let mut metadata = Metadata::new();
+        let task = Task {
+            name: Spanned {
+                item: "task".to_owned(),
+                span: Span::unknown(),
+            },
+            description: None,
+            depends: Vec::new(),
+            flags: TaskFlags::default(),
+            depends_decl_id: None,
+            decl_body: None,
+            run_body: None,
+        };
+        let task_id = metadata
+            .register_task("task".to_owned(), task, Span::unknown())
+            .unwrap();
+
+        for deps in dependencies {
+            let call_id = metadata
+                .register_task_call(task_id, Span::unknown(), Vec::new(), Vec::new())
+                .unwrap();
+            metadata
+                .task_call_metadata_mut(call_id)
+                .unwrap()
+                .dependencies = deps.to_vec();
+        }
+
+        metadata
+    }
+
+    /// A diamond: 0 depends on 1 and 2, which both depend on 3.
+    fn diamond() -> RunGraph {
+        RunGraph::new(&[0], &metadata(&[&[1, 2, 1], &[3], &[3], &[]]))
+    }
+
+    #[test]
+    fn test_new() {
+        let graph = diamond();
+        assert_eq!(&[0], graph.roots());
+        assert_eq!(&[3, 1, 2, 0], graph.order());
+        assert_eq!(&[1, 2], graph.dependencies(0));
+        assert_eq!(&[1, 2], graph.dependents(3));
+        assert!(graph.dependents(0).is_empty());
+        assert!(graph.contains(3));
+
+        // dependencies are run in the order they were declared
+        let graph = RunGraph::new(&[0], &metadata(&[&[3, 1, 2], &[], &[], &[]]));
+        assert_eq!(&[3, 1, 2], graph.dependencies(0));
+        assert_eq!(&[3, 1, 2, 0], graph.order());
+        assert_eq!(
+            vec![vec![3], vec![1], vec![2], vec![0]],
+            graph.stages(4, |_| false)
+        );
+
+        // only the task calls reachable from the roots are included
+        let graph = RunGraph::new(&[1, 2], &metadata(&[&[1, 2], &[3], &[], &[]]));
+        assert_eq!(&[3, 1, 2], graph.order());
+        assert!(!graph.contains(0));
+        assert!(graph.dependents(2).is_empty());
+    }
+
+    #[test]
+    fn test_ready() {
+        let graph = diamond();
+        let pending = HashSet::from([0, 1, 2]);
+        let finished = HashSet::from([3]);
+
+        assert_eq!(
+            vec![1, 2],
+            graph.ready(&pending, &finished, &[], 4, |_| true)
+        );
+        assert_eq!(vec![1], graph.ready(&pending, &finished, &[], 1, |_| true));
+
+        // running task calls count towards the number of jobs
+        let pending = HashSet::from([0, 2]);
+        assert_eq!(vec![2], graph.ready(&pending, &finished, &[1], 2, |_| true));
+        assert!(graph
+            .ready(&pending, &finished, &[1], 1, |_| true)
+            .is_empty());
+
+        // dependencies must have finished
+        let pending = HashSet::from([0, 2]);
+        let finished = HashSet::from([1, 3]);
+        assert_eq!(vec![2], graph.ready(&pending, &finished, &[], 4, |_| true));
+
+        // task calls that are not concurrent run alone
+        let pending = HashSet::from([0, 1, 2]);
+        let finished = HashSet::from([3]);
+        let is_concurrent = |c| c != 2;
+        assert_eq!(
+            vec![1],
+            graph.ready(&pending, &finished, &[], 4, is_concurrent)
+        );
+        let pending = HashSet::from([0, 2]);
+        assert!(graph
+            .ready(&pending, &finished, &[1], 4, is_concurrent)
+            .is_empty());
+        assert_eq!(
+            vec![2],
+            graph.ready(&pending, &finished, &[], 4, is_concurrent)
+        );
+    }
+
+    #[test]
+    fn test_stages() {
+        let graph = diamond();
+        assert_eq!(
+            vec![vec![3], vec![1, 2], vec![0]],
+            graph.stages(4, |_| true)
+        );
+        assert_eq!(
+            vec![vec![3], vec![1], vec![2], vec![0]],
+            graph.stages(1, |_| true)
+        );
+        assert_eq!(
+            vec![vec![3], vec![1], vec![2], vec![0]],
+            graph.stages(4, |c| c != 1)
+        );
+    }
+
+    #[test]
+    fn test_with_dependencies() {
+        let graph = diamond();
+        assert_eq!(HashSet::from([0, 1, 2, 3]), graph.with_dependents(3));
+        assert_eq!(HashSet::from([0, 1]), graph.with_dependents(1));
+        assert_eq!(HashSet::from([0]), graph.with_dependents(0));
+        assert_eq!(HashSet::from([1, 3]), graph.with_dependencies(1));
+        assert_eq!(HashSet::from([0, 1, 2, 3]), graph.with_dependencies(0));
+    }
+}
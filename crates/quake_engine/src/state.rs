@@ -1,7 +1,10 @@
 use std::collections::BTreeMap;
 use std::fmt::Debug;
+use std::num::NonZeroUsize;
 use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use nu_protocol::engine::{EngineState, Stack};
 use nu_protocol::{Span, Value};
@@ -30,12 +33,27 @@ type ScopeId = usize;
 pub struct State {
     pub metadata: Metadata,
     pub errors: Vec<ErrReport>,
+    /// The default maximum number of tasks to run at once, as set by the build
+    /// script.
+    pub jobs: Option<NonZeroUsize>,
+    /// The default timeout for tasks, as set by the build script.
+    pub timeout: Option<Duration>,
     scopes: BTreeMap<ScopeId, Scope>,
+    project_root: PathBuf,
 }
 
 impl State {
-    pub fn new() -> Self {
-        Default::default()
+    pub fn new(project_root: PathBuf) -> Self {
+        Self {
+            project_root,
+            ..Default::default()
+        }
+    }
+
+    /// The root directory of the project, which paths declared by tasks are
+    /// relative to.
+    pub fn project_root(&self) -> &Path {
+        &self.project_root
     }
 
     pub fn from_engine_state(
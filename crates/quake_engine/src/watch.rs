@@ -0,0 +1,127 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher as _};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+use quake_core::prelude::*;
+
+use crate::events::EventEmitter;
+
+/// How long to wait for further changes after a change is first observed, so
+/// that bursts of changes (e.g. from editors or version control) are handled
+/// together.
+const DEBOUNCE_DURATION: Duration = Duration::from_millis(100);
+
+/// Watches a set of files for changes.
+///
+/// As files may not yet exist (or may be deleted and recreated), the parent
+/// directories of each file are watched instead, and changes to any other
+/// files inside of them are ignored.
+pub struct Watcher {
+    watcher: RecommendedWatcher,
+    receiver: UnboundedReceiver<PathBuf>,
+    files: HashSet<PathBuf>,
+    dirs: HashSet<PathBuf>,
+    changed: Vec<PathBuf>,
+    events: EventEmitter,
+}
+
+impl Watcher {
+    pub fn new(events: EventEmitter) -> EngineResult<Self> {
+        let (sender, receiver) = unbounded_channel();
+
+        let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+
+            // ignore reads, which happen whenever tasks use their sources
+            if !(event.kind.is_create() || event.kind.is_modify() || event.kind.is_remove()) {
+                return;
+            }
+
+            for path in event.paths {
+                // the receiver has been dropped, nothing left to do
+                if sender.send(path).is_err() {
+                    return;
+                }
+            }
+        })
+        .map_err(|err| EngineError::internal(format!("failed to create file watcher: {err}")))?;
+
+        Ok(Self {
+            watcher,
+            receiver,
+            files: HashSet::new(),
+            dirs: HashSet::new(),
+            changed: Vec::new(),
+            events,
+        })
+    }
+
+    /// Watch the given files for changes.
+    pub fn watch(&mut self, files: impl IntoIterator<Item = PathBuf>) {
+        for file in files {
+            let dir = match file.parent() {
+                Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+                Some(dir) => dir,
+                None => continue,
+            };
+
+            if !self.dirs.contains(dir) {
+                if let Err(err) = self.watcher.watch(dir, RecursiveMode::NonRecursive) {
+                    self.events.log_warning(
+                        "watch",
+                        &format!("failed to watch {dir}: {err}", dir = dir.display()),
+                    );
+                    continue;
+                }
+
+                self.dirs.insert(dir.to_owned());
+            }
+
+            self.files.insert(file);
+        }
+    }
+
+    /// Stop watching all files.
+    pub fn unwatch_all(&mut self) {
+        for dir in self.dirs.drain() {
+            let _ = self.watcher.unwatch(&dir);
+        }
+
+        self.files.clear();
+        self.changed.clear();
+    }
+
+    /// Wait until any watched files have changed, returning the paths of all
+    /// changed files.
+    ///
+    /// This is cancel safe, in that no changes will be lost if the returned
+    /// future is dropped before completion.
+    pub async fn changed(&mut self) -> Vec<PathBuf> {
+        // wait for the first change to a watched file
+        while self.changed.is_empty() {
+            let Some(path) = self.receiver.recv().await else {
+                // the watcher has stopped, so no more changes will occur
+                return std::future::pending().await;
+            };
+
+            if self.files.contains(&path) {
+                self.changed.push(path);
+            }
+        }
+
+        // collect any further changes made shortly afterwards
+        tokio::time::sleep(DEBOUNCE_DURATION).await;
+        while let Ok(path) = self.receiver.try_recv() {
+            if self.files.contains(&path) && !self.changed.contains(&path) {
+                self.changed.push(path);
+            }
+        }
+
+        std::mem::take(&mut self.changed)
+    }
+}
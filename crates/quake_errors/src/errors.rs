@@ -1,6 +1,9 @@
 //! Diagnostics emitted by quake.
 
-use nu_protocol::Span;
+use std::time::Duration;
+
+use miette::LabeledSpan;
+use nu_protocol::{ShellError, Span};
 
 pub const QUAKE_OTHER_ERROR_CODE: &str = "quake::other";
 
@@ -43,13 +46,16 @@ make_errors! {
     )]
     pub struct BuildScriptNotFound;
 
-    // TODO add "did you mean?" or list available tasks
     #[error("Task not found: {name}")]
-    #[diagnostic(code(quake::task_not_found), help("Use `quake list` to list available tasks"))]
+    #[diagnostic(code(quake::task_not_found))]
     pub struct TaskNotFound {
         pub name: String,
         #[label("task referenced here")]
         pub span: Option<Span>,
+        /// Suggestions of similarly named tasks, or otherwise the available
+        /// tasks (see [`TaskNotFound::new`]).
+        #[help]
+        pub help: String,
     }
 
     #[error("Task already defined: {name}")]
@@ -72,6 +78,68 @@ make_errors! {
         pub span: Span,
     }
 
+    #[error("Dependency cycle detected: {}", .cycle.join(" -> "))]
+    #[diagnostic(
+        code(quake::dependency_cycle),
+        help("Remove one of the dependencies to break the cycle")
+    )]
+    pub struct DependencyCycle {
+        /// The names of the tasks in the cycle, starting and ending with the
+        /// same task.
+        pub cycle: Vec<String>,
+        #[label(collection)]
+        pub spans: Vec<LabeledSpan>,
+    }
+
+    #[error("Build failed: {}", summarize(&.tasks))]
+    #[diagnostic(code(quake::build_failed))]
+    pub struct BuildFailed {
+        /// Every task call that did not finish as a result of the failure.
+        #[related]
+        pub tasks: Vec<TaskCallFailure>,
+    }
+
+    pub enum TaskCallFailure {
+        /// The task call ran and failed.
+        #[error("Task failed: {task}")]
+        #[diagnostic(code(quake::task_failed))]
+        Failed {
+            task: String,
+            #[label("task called here")]
+            span: Span,
+            #[related]
+            error: Option<ShellError>,
+        },
+        /// The task call ran for longer than its timeout, and was stopped.
+        #[error("Task timed out after {:.1}s: {task}", .elapsed.as_secs_f64())]
+        #[diagnostic(
+            code(quake::task_timed_out),
+            help("Increase the timeout with `--timeout` (or `default-timeout` in the build script)")
+        )]
+        TimedOut {
+            task: String,
+            #[label("task called here")]
+            span: Span,
+            elapsed: Duration,
+        },
+        /// The task call was running, but was stopped (or interrupted).
+        #[error("Task cancelled: {task}")]
+        #[diagnostic(code(quake::task_cancelled), severity(warning))]
+        Cancelled {
+            task: String,
+            #[label("task called here")]
+            span: Span,
+        },
+        /// The task call was never started.
+        #[error("Task not started: {task}")]
+        #[diagnostic(code(quake::task_not_started), severity(advice))]
+        NotStarted {
+            task: String,
+            #[label("task called here")]
+            span: Span,
+        },
+    }
+
     #[error("Invalid scope for command")]
     #[diagnostic(
         code(quake::invalid_scope),
@@ -93,6 +161,82 @@ make_errors! {
     }
 }
 
+impl TaskNotFound {
+    /// The maximum number of tasks to suggest or list.
+    const MAX_TASKS: usize = 5;
+    /// The minimum similarity of a task name to the name given for it to be
+    /// suggested, between 0 and 1.
+    const SIMILARITY_THRESHOLD: f64 = 0.8;
+
+    /// Create an error for a missing task, suggesting the `tasks` with the most
+    /// similar names to `name` (if any), and otherwise listing them.
+    pub fn new<'a>(
+        name: impl Into<String>,
+        span: Option<Span>,
+        tasks: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        let name = name.into();
+        let tasks = tasks.into_iter().collect::<Vec<_>>();
+
+        let mut similar = tasks
+            .iter()
+            .map(|&task| (strsim::jaro_winkler(&name, task), task))
+            .filter(|(similarity, _)| *similarity >= Self::SIMILARITY_THRESHOLD)
+            .collect::<Vec<_>>();
+        similar.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+        let quote = |tasks: &[&str]| {
+            (tasks.iter().take(Self::MAX_TASKS))
+                .map(|task| format!("`{task}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let help = match similar.as_slice() {
+            [(_, task)] => format!("Did you mean `{task}`?"),
+            [_, ..] => {
+                let similar = similar.iter().map(|(_, task)| *task).collect::<Vec<_>>();
+                format!("Did you mean one of {}?", quote(&similar))
+            }
+            [] if tasks.is_empty() => "No tasks are defined in the build script".to_owned(),
+            [] if tasks.len() > Self::MAX_TASKS => format!(
+                "Available tasks include {} (use `quake list` to list all tasks)",
+                quote(&tasks)
+            ),
+            [] => format!("Available tasks: {}", quote(&tasks)),
+        };
+
+        Self { name, span, help }
+    }
+}
+
+/// Summarize the task calls of a failed build, e.g. `build, test failed, 1
+/// cancelled`.
+fn summarize(tasks: &[TaskCallFailure]) -> String {
+    let (mut failed, mut cancelled, mut not_started) = (Vec::new(), 0, 0);
+    for task in tasks {
+        match task {
+            TaskCallFailure::Failed { task, .. } | TaskCallFailure::TimedOut { task, .. } => {
+                failed.push(task.as_str())
+            }
+            TaskCallFailure::Cancelled { .. } => cancelled += 1,
+            TaskCallFailure::NotStarted { .. } => not_started += 1,
+        }
+    }
+
+    let mut parts = Vec::new();
+    if !failed.is_empty() {
+        parts.push(format!("{} failed", failed.join(", ")));
+    }
+    if cancelled > 0 {
+        parts.push(format!("{cancelled} cancelled"));
+    }
+    if not_started > 0 {
+        parts.push(format!("{not_started} not started"));
+    }
+    parts.join(", ")
+}
+
 #[cfg(test)]
 mod tests {
     use anstream::adapter::strip_str;
@@ -159,4 +303,23 @@ mod tests {
             }))
         );
     }
+
+    #[test]
+    fn test_task_not_found_help() {
+        use super::TaskNotFound;
+
+        let tasks = ["build", "bundle", "clean", "render-docs"];
+        let help = |name| TaskNotFound::new(name, None, tasks).help;
+
+        assert_eq!("Did you mean `build`?", help("biuld"));
+        assert_eq!("Did you mean `render-docs`?", help("render-doc"));
+        assert_eq!(
+            "Available tasks: `build`, `bundle`, `clean`, `render-docs`",
+            help("xyz")
+        );
+        assert_eq!(
+            "No tasks are defined in the build script",
+            TaskNotFound::new("build", None, []).help
+        );
+    }
 }
@@ -119,8 +119,10 @@ pub enum EngineError {
     ParseFailed,
     #[error("failed to evaluate build script")]
     EvalFailed,
-    #[error("task failed")]
-    TaskFailed { task_name: String },
+    #[error(transparent)]
+    BuildFailed(#[from] errors::BuildFailed),
+    #[error("interrupted")]
+    Interrupted,
     #[error("internal error: {message}")]
     Internal { message: String },
     #[error(transparent)]
@@ -140,7 +142,8 @@ impl EngineError {
             | EngineError::ParseFailed
             | EngineError::EvalFailed
             | EngineError::Other { .. } => exit_codes::CAUSE_OTHER,
-            EngineError::TaskFailed { .. } => exit_codes::CAUSE_USER,
+            EngineError::BuildFailed(_) => exit_codes::CAUSE_USER,
+            EngineError::Interrupted => exit_codes::CAUSE_INTERRUPTED,
             EngineError::Internal { .. } => exit_codes::CAUSE_INTERNAL,
         }
         .into()
@@ -157,6 +160,10 @@ pub mod exit_codes {
     /// error, a bad build script, or simply an enternal error.
     pub const CAUSE_OTHER: u8 = 127;
 
+    /// Cause for builds stopped by the user (e.g. with Ctrl-C), following the
+    /// convention of 128 + `SIGINT`.
+    pub const CAUSE_INTERRUPTED: u8 = 130;
+
     /// Cause for errors that originated internally--almost always a bug.
     pub const CAUSE_INTERNAL: u8 = 255;
 }
@@ -1,36 +1,158 @@
 #![feature(iter_intersperse)]
 
+use std::collections::HashSet;
 use std::env;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use clap::builder::PathBufValueParser;
 use clap::ArgMatches;
+use humantime::format_rfc3339_seconds;
 use serde_json::to_string as to_json;
 
 use quake_core::prelude::*;
-use quake_core::utils::get_init_cwd;
-use quake_engine::{Engine, EngineOptions};
+use quake_core::utils::{get_init_cwd, Dirtiness, FileTimestamp};
+use quake_core::JOBS_ENV;
+use quake_engine::info::{ParamInfo, ParamKind, TaskInfo};
+use quake_engine::plan::Plan;
+use quake_engine::{Engine, EngineOptions, ForceMode};
 
-fn parse_args() -> ArgMatches {
+fn command() -> clap::Command {
     use clap::*;
+
+    // the automatic `--help` is disabled so that `quake <TASK> --help` can show the
+    // help for the task, so it must be added back to each subcommand
+    let help = || {
+        Arg::new("help")
+            .short('h')
+            .long("help")
+            .action(ArgAction::Help)
+            .help("Print help")
+    };
+
+    // options affecting which tasks are dirty, also accepted by `quake why`
+    let dirtiness = || {
+        [
+            Arg::new("force")
+                .long("force")
+                .action(ArgAction::SetTrue)
+                .help("Execute tasks regardless of initial dirtiness checks"),
+            Arg::new("force-task")
+                .long("force-task")
+                .value_name("TASK")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .conflicts_with("force")
+                .help("Execute a specific task regardless of initial dirtiness checks"),
+            Arg::new("content-hash")
+                .long("content-hash")
+                .action(ArgAction::SetTrue)
+                .help("Detect changed files by their contents instead of modification times"),
+        ]
+    };
+
     Command::new("quake")
         .about("quake: a meta-build system powered by nushell")
         .version(crate_version!())
         .color(ColorChoice::Never)
         .max_term_width(100)
         .override_usage(
-            "quake [OPTIONS] <TASK> [--] [TASK_ARGS]\n       \
+            "quake [OPTIONS] <TASK> [--] [TASK_ARGS] [, <TASK> [TASK_ARGS]]...\n       \
              quake [OPTIONS]",
         )
+        .after_help(
+            "Multiple tasks can be run at once by separating them with `,` (e.g. `quake build \
+             --release , test`), which may be omitted after tasks that take no arguments or \
+             before the names of other tasks (e.g. `quake lint test build`).",
+        )
         .arg_required_else_help(true)
         .disable_help_subcommand(true)
+        .disable_help_flag(true)
         .args_conflicts_with_subcommands(true)
         .subcommand_negates_reqs(true)
         .subcommand_help_heading("Subcommands")
         .subcommands([
-            Command::new("list").about("List the available tasks"),
-            Command::new("inspect").about("Dump build script metadata as JSON"),
+            Command::new("list")
+                .about("List the available tasks")
+                .args([
+                    Arg::new("all")
+                        .short('a')
+                        .long("all")
+                        .action(ArgAction::SetTrue)
+                        .help("Include subtasks defined inside of other tasks"),
+                    help(),
+                ]),
+            Command::new("inspect")
+                .about("Dump build script metadata as JSON")
+                .args([
+                    Arg::new("task")
+                        .value_name("TASK")
+                        .help("Evaluate the declaration of a task call and its dependencies"),
+                    Arg::new("task-args")
+                        .value_name("TASK_ARGS")
+                        .trailing_var_arg(true)
+                        .allow_hyphen_values(true)
+                        .num_args(0..)
+                        .requires("task")
+                        .help("Arguments to pass to the task"),
+                    help(),
+                ]),
+            Command::new("graph")
+                .about("Export the dependency graph of task calls")
+                .args([
+                    Arg::new("format")
+                        .short('f')
+                        .long("format")
+                        .value_name("FORMAT")
+                        .value_parser(["dot", "mermaid", "json"])
+                        .help("The format of the graph [default: dot, or json with --json]"),
+                    Arg::new("task")
+                        .value_name("TASK")
+                        .help("Export the graph of a task call (defaults to every task)"),
+                    Arg::new("task-args")
+                        .value_name("TASK_ARGS")
+                        .trailing_var_arg(true)
+                        .allow_hyphen_values(true)
+                        .num_args(0..)
+                        .requires("task")
+                        .help("Arguments to pass to the task"),
+                    help(),
+                ]),
+            Command::new("why")
+                .about("Explain why tasks would run or be skipped")
+                .args([
+                    Arg::new("task")
+                        .value_name("TASK")
+                        .required(true)
+                        .help("The task call to explain, along with its dependencies"),
+                    Arg::new("task-args")
+                        .value_name("TASK_ARGS")
+                        .trailing_var_arg(true)
+                        .allow_hyphen_values(true)
+                        .num_args(0..)
+                        .help("Arguments to pass to the task"),
+                    help(),
+                ])
+                .next_help_heading("Evaluation modes")
+                .args(dirtiness()),
+            Command::new("help")
+                .about("Show the usage of a task")
+                .args([
+                    Arg::new("task")
+                        .value_name("TASK")
+                        .required(true)
+                        .help("The task to show the usage of"),
+                    help(),
+                ]),
         ])
+        .arg(
+            Arg::new("help")
+                .short('h')
+                .long("help")
+                .action(ArgAction::SetTrue)
+                .help("Print help"),
+        )
         .next_help_heading("Environment")
         .args([Arg::new("project")
             .long("project")
@@ -56,14 +178,36 @@ fn parse_args() -> ArgMatches {
         ])
         .next_help_heading("Evaluation modes")
         .args([
-            Arg::new("force")
-                .long("force")
+            Arg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .value_name("N")
+                .value_parser(value_parser!(NonZeroUsize))
+                .help(format!(
+                    "Run at most N tasks at once (overrides {JOBS_ENV})"
+                )),
+            Arg::new("keep-going")
+                .short('k')
+                .long("keep-going")
                 .action(ArgAction::SetTrue)
-                .help("Execute tasks regardless of initial dirtiness checks"),
+                .help("Keep running tasks unaffected by a failed task"),
+            Arg::new("timeout")
+                .long("timeout")
+                .value_name("DURATION")
+                .value_parser(parse_duration)
+                .help("Stop any task running for longer than DURATION (e.g. 90s, 5min)"),
+        ])
+        .args(dirtiness())
+        .args([
             Arg::new("watch")
                 .long("watch")
                 .action(ArgAction::SetTrue)
                 .help("Run the task, and re-run whenever sources have changed"),
+            Arg::new("dry-run")
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("watch")
+                .help("Show the tasks that would run, and in what order, without running them"),
         ])
         .args([
             Arg::new("task").value_name("TASK").hide(true),
@@ -74,11 +218,309 @@ fn parse_args() -> ArgMatches {
                 .num_args(0..)
                 .hide(true),
         ])
-        .get_matches()
+}
+
+/// Parse a positive duration, e.g. `90s` or `1m30s` (see
+/// [`humantime::parse_duration`]).
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    match humantime::parse_duration(value) {
+        Ok(duration) if duration.is_zero() => Err("duration must be positive".to_owned()),
+        result => result.map_err(|err| err.to_string()),
+    }
+}
+
+/// The argument separating task calls on the command line, e.g. `quake build
+/// --release , test`.
+const CALL_SEPARATOR: &str = ",";
+
+/// Characters with special meaning to nushell, which must be quoted to be
+/// passed on literally.
+const SPECIAL_CHARS: &[char] = &[
+    '$', '(', ')', '[', ']', '{', '}', '|', ';', '#', '\\', '`', '"', '\'',
+];
+
+/// Quote a command line argument so that it is parsed as a single literal
+/// argument when passed on to the task.
+///
+/// Only the value of a flag given as `--flag=value` is quoted, so that it is
+/// still parsed as a flag.
+fn quote_arg(arg: &str) -> String {
+    match arg.split_once('=') {
+        Some((flag, value)) if flag.starts_with('-') && !needs_quoting(flag) => {
+            format!("{flag}={}", quote_str(value))
+        }
+        _ => quote_str(arg),
+    }
+}
+
+fn needs_quoting(s: &str) -> bool {
+    s.is_empty() || s.contains(|c: char| c.is_whitespace() || SPECIAL_CHARS.contains(&c))
+}
+
+/// Quote a string as a nushell double-quoted string if it contains any
+/// whitespace or [special characters](SPECIAL_CHARS).
+fn quote_str(s: &str) -> String {
+    if !needs_quoting(s) {
+        return s.to_owned();
+    }
+
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Split the tasks and their arguments given on the command line into task
+/// calls, each given as the name of the task and its arguments as a single
+/// string.
+///
+/// Task calls are separated by [`CALL_SEPARATOR`], except after tasks that
+/// take no parameters and before the names of other tasks (as described by
+/// `tasks`), which start the next task call. As such, the name of a task can
+/// only be passed as an argument as the value of a flag (e.g. `--task=build`).
+fn task_calls(matches: &ArgMatches, tasks: &[TaskInfo]) -> Vec<(String, String)> {
+    let takes_params = |name: &str| {
+        tasks
+            .iter()
+            .find(|t| t.name == name)
+            .is_none_or(|t| !t.params.is_empty())
+    };
+    let is_task = |name: &str| tasks.iter().any(|t| t.name == name && !t.subtask);
+
+    let words = (matches.get_one::<String>("task").into_iter())
+        .chain(
+            matches
+                .get_many::<String>("task-args")
+                .into_iter()
+                .flatten(),
+        )
+        .filter(|s| *s != "--");
+
+    let mut calls: Vec<(&String, Vec<&String>)> = Vec::new();
+    let mut separated = true;
+    for word in words {
+        if word == CALL_SEPARATOR {
+            separated = true;
+            continue;
+        }
+
+        match calls.last_mut() {
+            Some((task, args)) if !separated && takes_params(task) && !is_task(word) => {
+                args.push(word)
+            }
+            _ => calls.push((word, Vec::new())),
+        }
+        separated = false;
+    }
+
+    calls
+        .into_iter()
+        .map(|(task, args)| {
+            let args = (args.into_iter())
+                .map(|s| quote_arg(s))
+                .intersperse(String::from(" "))
+                .collect();
+            (task.clone(), args)
+        })
+        .collect()
+}
+
+/// Create a task call for every task that can be called without arguments,
+/// excluding subtasks, along with the names of the tasks that require
+/// arguments.
+fn calls_without_arguments(tasks: &[TaskInfo]) -> (Vec<(String, String)>, Vec<&str>) {
+    let (callable, skipped): (Vec<_>, Vec<_>) = tasks
+        .iter()
+        .filter(|t| !t.subtask)
+        .partition(|t| !t.requires_arguments());
+
+    let calls = callable
+        .into_iter()
+        .map(|t| (t.name.clone(), String::new()))
+        .collect();
+    let skipped = skipped.into_iter().map(|t| t.name.as_str()).collect();
+    (calls, skipped)
+}
+
+/// Print a task as an entry in `quake list`.
+fn print_task(task: &TaskInfo) {
+    let flags = [(task.concurrent, "concurrent"), (task.pure, "pure")]
+        .into_iter()
+        .filter_map(|(set, flag)| set.then_some(flag))
+        .collect::<Vec<_>>();
+
+    if flags.is_empty() {
+        println!("- {}", task.usage());
+    } else {
+        println!("- {} ({})", task.usage(), flags.join(", "));
+    }
+
+    if let Some(summary) = task.summary() {
+        println!("    {summary}");
+    }
+}
+
+/// Print the usage of a task, as shown by `quake help <TASK>`.
+fn print_task_help(task: &TaskInfo) {
+    if let Some(description) = &task.description {
+        println!("{description}\n");
+    }
+
+    println!("Usage: quake {}", task.usage());
+
+    let (params, flags): (Vec<_>, Vec<_>) = task
+        .params
+        .iter()
+        .partition(|p| !matches!(p.kind, ParamKind::Flag | ParamKind::RequiredFlag));
+
+    print_params("Parameters", &params, |p| match &p.shape {
+        Some(shape) => format!("{}: {shape}", p.usage()),
+        None => p.usage(),
+    });
+    print_params("Flags", &flags, |p| {
+        let short = p.short.map(|s| format!("-{s}, ")).unwrap_or_default();
+        let name = match &p.shape {
+            Some(shape) => format!("--{} <{shape}>", p.name),
+            None => format!("--{}", p.name),
+        };
+        format!("{short:>4}{name}")
+    });
+
+    if !task.depends.is_empty() {
+        println!("\nDependencies: {}", task.depends.join(", "));
+    }
+}
+
+/// Print a section of parameters in the help for a task, with their names (as
+/// given by `name`) aligned.
+fn print_params(heading: &str, params: &[&ParamInfo], name: impl Fn(&ParamInfo) -> String) {
+    if params.is_empty() {
+        return;
+    }
+
+    println!("\n{heading}:");
+    let names = params.iter().map(|p| name(p)).collect::<Vec<_>>();
+    let width = names.iter().map(String::len).max().unwrap_or_default();
+    for (param, name) in params.iter().zip(names) {
+        let mut line = format!("  {name:width$}  {}", param.description);
+        if let Some(default) = &param.default {
+            line.push_str(&format!(" (default: {default})"));
+        }
+        println!("{}", line.trim_end());
+    }
+}
+
+/// Print the plan of a build, as shown by `quake --dry-run <TASK>`.
+fn print_plan(plan: &Plan) {
+    for (i, stage) in plan.stages().enumerate() {
+        if stage.len() > 1 {
+            println!("Stage {} (concurrently):", i + 1);
+        } else {
+            println!("Stage {}:", i + 1);
+        }
+
+        for call in stage {
+            let action = if call.run { "run" } else { "skip" };
+            println!("  {action:<4}  {} ({})", call.call, call.dirtiness);
+        }
+    }
+
+    let run = plan.calls.iter().filter(|c| c.run).count();
+    println!(
+        "\n{run} task call(s) would run, {} would be skipped",
+        plan.calls.len() - run
+    );
+}
+
+/// Print why each task call in the plan of a build would run or be skipped, as
+/// shown by `quake why <TASK>`.
+fn print_explanation(plan: &Plan) {
+    // task calls that would be skipped may still run if any of their dependencies
+    // do
+    let mut may_run = HashSet::new();
+    for call in &plan.calls {
+        if call.run || call.dependencies.iter().any(|d| may_run.contains(d)) {
+            may_run.insert(call.call_id);
+        }
+    }
+
+    for call in &plan.calls {
+        if call.run {
+            println!("{}: would run", call.call);
+        } else {
+            println!("{}: would be skipped", call.call);
+        }
+
+        println!("  {}", explain(&call.dirtiness));
+        if !call.run && may_run.contains(&call.call_id) {
+            println!("  (but may run if any of its dependencies modify its sources)");
+        }
+    }
+}
+
+/// Explain the dirtiness of a task call in detail, including the modification
+/// times of any files compared.
+fn explain(dirtiness: &Dirtiness) -> String {
+    let file = |file: &FileTimestamp| {
+        format!(
+            "`{}` (modified {})",
+            file.path.display(),
+            format_rfc3339_seconds(file.modified)
+        )
+    };
+
+    match dirtiness {
+        Dirtiness::UpToDate {
+            source: Some(source),
+            artifact: Some(artifact),
+        } => format!(
+            "the newest source {} is not newer than the newest artifact {}",
+            file(source),
+            file(artifact)
+        ),
+        Dirtiness::UpToDate {
+            source: None,
+            artifact: Some(artifact),
+        } => format!(
+            "none of its sources exist, and the newest artifact is {}",
+            file(artifact)
+        ),
+        Dirtiness::UpToDate { .. } => "none of its sources or artifacts exist".to_owned(),
+        Dirtiness::Unchanged => {
+            "none of its sources or artifacts have changed since its last successful run".to_owned()
+        }
+        Dirtiness::Forced => "it was forced to run (with --force or --force-task)".to_owned(),
+        Dirtiness::NoSources => "it declares no sources, so it is always run".to_owned(),
+        Dirtiness::NoArtifacts => "it declares no artifacts, so it is always run".to_owned(),
+        Dirtiness::SourceNewer { source, artifact } => format!(
+            "source {} is newer than the newest artifact {}",
+            file(source),
+            file(artifact)
+        ),
+        Dirtiness::NeverRun => "it has never been run before".to_owned(),
+        dirtiness => dirtiness.to_string(),
+    }
 }
 
 fn main() -> CliResult {
-    let matches = parse_args();
+    let matches = command().get_matches();
+
+    if matches.get_flag("help") && !matches.contains_id("task") {
+        command()
+            .print_help()
+            .map_err(|err| error!("Failed to print help: {err}"))?;
+        return CliResult::success();
+    }
 
     let project = {
         if let Some(project_root) = matches.get_one::<PathBuf>("project") {
@@ -93,11 +535,41 @@ fn main() -> CliResult {
 
     let json = matches.get_flag("json");
 
+    // options affecting dirtiness may also be given to `quake why`
+    let dirtiness_matches = match matches.subcommand() {
+        Some(("why", matches)) => matches,
+        _ => &matches,
+    };
+
+    let force = if dirtiness_matches.get_flag("force") {
+        ForceMode::All
+    } else if let Some(tasks) = dirtiness_matches.get_many::<String>("force-task") {
+        ForceMode::Tasks(tasks.cloned().collect())
+    } else {
+        ForceMode::None
+    };
+
+    let jobs = match matches.get_one::<NonZeroUsize>("jobs") {
+        Some(jobs) => Some(*jobs),
+        None => match env::var(JOBS_ENV) {
+            Ok(value) => Some(
+                value
+                    .parse()
+                    .map_err(|_| error!("Invalid value for {JOBS_ENV}: {value:?}"))?,
+            ),
+            Err(_) => None,
+        },
+    };
+
     let options = EngineOptions {
         quiet: matches.get_flag("quiet"),
         json,
-        force: matches.get_flag("force"),
+        force,
         watch: matches.get_flag("watch"),
+        content_hash: dirtiness_matches.get_flag("content-hash"),
+        jobs,
+        keep_going: matches.get_flag("keep-going"),
+        timeout: matches.get_one::<Duration>("timeout").copied(),
     };
 
     let mut engine = Engine::load(project, options)?;
@@ -105,20 +577,40 @@ fn main() -> CliResult {
     match matches.subcommand() {
         None => {
             let task = matches.get_one::<String>("task").unwrap();
-            let args: String = matches
-                .get_many::<String>("task-args")
-                .map(|args| {
-                    args.filter(|s| *s != "--")
-                        .cloned()
-                        .intersperse(String::from(" "))
-                        .collect()
-                })
-                .unwrap_or_default();
-            engine.run(task, &args)?;
-        }
-        Some(("list", _)) => {
-            let metadata = engine.metadata();
-            let tasks: Vec<_> = metadata.task().map(|t| &t.name.item).collect();
+            if matches.get_flag("help") {
+                print_task_help(&engine.describe_task(task)?);
+            } else {
+                let calls = task_calls(&matches, &engine.describe_tasks());
+                if matches.get_flag("dry-run") {
+                    let plan = engine.plan(&calls)?;
+                    if json {
+                        println!("{}", to_json(&plan).unwrap());
+                    } else {
+                        print_plan(&plan);
+                    }
+                } else {
+                    engine.run(&calls)?;
+                }
+            }
+        }
+        Some(("help", matches)) => {
+            let task = matches.get_one::<String>("task").unwrap();
+            print_task_help(&engine.describe_task(task)?);
+        }
+        Some(("list", matches)) => {
+            let all = matches.get_flag("all");
+            if all {
+                // subtasks are only defined once the declaration bodies of their parents have
+                // been evaluated
+                let (calls, _) = calls_without_arguments(&engine.describe_tasks());
+                engine.prepare_calls(&calls)?;
+            }
+
+            let tasks: Vec<_> = engine
+                .describe_tasks()
+                .into_iter()
+                .filter(|t| all || !t.subtask)
+                .collect();
 
             if json {
                 println!("{}", to_json(&tasks).unwrap());
@@ -127,11 +619,45 @@ fn main() -> CliResult {
             } else {
                 println!("Available tasks:");
                 for task in tasks {
-                    println!("- {task}");
+                    print_task(&task);
+                }
+            }
+        }
+        Some(("graph", matches)) => {
+            let tasks = engine.describe_tasks();
+            let calls = if matches.contains_id("task") {
+                task_calls(matches, &tasks)
+            } else {
+                let (calls, skipped) = calls_without_arguments(&tasks);
+                if !skipped.is_empty() && !json {
+                    log_warning!("skipping tasks that require arguments", skipped.join(", "));
                 }
+                calls
+            };
+
+            let graph = engine.graph(&calls)?;
+            let format = matches.get_one::<String>("format").map(String::as_str);
+            match format.unwrap_or(if json { "json" } else { "dot" }) {
+                "dot" => print!("{}", graph.to_dot()),
+                "mermaid" => print!("{}", graph.to_mermaid()),
+                _ => println!("{}", to_json(&graph).unwrap()),
             }
         }
-        Some(("inspect", _)) => {
+        Some(("why", matches)) => {
+            let calls = task_calls(matches, &engine.describe_tasks());
+            let plan = engine.plan(&calls)?;
+            if json {
+                println!("{}", to_json(&plan).unwrap());
+            } else {
+                print_explanation(&plan);
+            }
+        }
+        Some(("inspect", matches)) => {
+            if matches.contains_id("task") {
+                let calls = task_calls(matches, &engine.describe_tasks());
+                engine.prepare_calls(&calls)?;
+            }
+
             println!("{}", to_json(&engine.metadata().clone()).unwrap());
         }
         Some((name, _)) => {
@@ -141,3 +667,111 @@ fn main() -> CliResult {
 
     CliResult::success()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(name: &str, params: &[&str]) -> TaskInfo {
+        let params = params
+            .iter()
+            .map(|name| ParamInfo {
+                name: (*name).to_owned(),
+                kind: ParamKind::Optional,
+                short: None,
+                shape: Some("string".to_owned()),
+                description: String::new(),
+                default: None,
+            })
+            .collect();
+
+        TaskInfo {
+            name: name.to_owned(),
+            description: None,
+            concurrent: false,
+            pure: false,
+            subtask: false,
+            params,
+            depends: Vec::new(),
+        }
+    }
+
+    fn calls(args: &[&str], tasks: &[TaskInfo]) -> Vec<(String, String)> {
+        let matches = command()
+            .try_get_matches_from(["quake"].iter().chain(args))
+            .unwrap();
+        task_calls(&matches, tasks)
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(Ok(Duration::from_secs(90)), parse_duration("90s"));
+        assert_eq!(Ok(Duration::from_secs(90)), parse_duration("1m30s"));
+        assert_eq!(Ok(Duration::from_secs(300)), parse_duration("5min"));
+        assert_eq!(Ok(Duration::from_millis(500)), parse_duration("500ms"));
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("90").is_err());
+        assert!(parse_duration("soon").is_err());
+    }
+
+    #[test]
+    fn test_quote_arg() {
+        assert_eq!("release", quote_arg("release"));
+        assert_eq!("--release", quote_arg("--release"));
+        assert_eq!("-j4", quote_arg("-j4"));
+        assert_eq!(r#""""#, quote_arg(""));
+        assert_eq!(r#""hello world""#, quote_arg("hello world"));
+        assert_eq!(r#""$HOME""#, quote_arg("$HOME"));
+        assert_eq!(r#""(ls)""#, quote_arg("(ls)"));
+        assert_eq!(r#""a;b""#, quote_arg("a;b"));
+        assert_eq!(r#""say \"hi\"""#, quote_arg(r#"say "hi""#));
+        assert_eq!(r#""C:\\Users""#, quote_arg(r"C:\Users"));
+        assert_eq!(r#""a\nb\tc""#, quote_arg("a\nb\tc"));
+
+        // only the value of a flag is quoted
+        assert_eq!("--target=x86_64", quote_arg("--target=x86_64"));
+        assert_eq!(r#"--target="x86 64""#, quote_arg("--target=x86 64"));
+        assert_eq!(r#"--target="""#, quote_arg("--target="));
+        assert_eq!(r#""a=b c""#, quote_arg("a=b c"));
+        assert_eq!(r#""--a b=c""#, quote_arg("--a b=c"));
+    }
+
+    #[test]
+    fn test_task_calls() {
+        let tasks = [task("build", &["target"]), task("clean", &[])];
+        let call = |task: &str, args: &str| (task.to_owned(), args.to_owned());
+
+        assert_eq!(vec![call("build", "")], calls(&["build"], &tasks));
+        assert_eq!(
+            vec![call("build", r#"--release "x86 64""#)],
+            calls(&["build", "--release", "x86 64"], &tasks)
+        );
+        assert_eq!(
+            vec![call("build", "a"), call("build", "b")],
+            calls(&["build", "a", ",", "build", "b"], &tasks)
+        );
+
+        // tasks without parameters are followed directly by the next task
+        assert_eq!(
+            vec![call("clean", ""), call("build", "a")],
+            calls(&["clean", "build", "a"], &tasks)
+        );
+        assert_eq!(
+            vec![call("clean", ""), call("clean", "")],
+            calls(&["clean", ",", "clean"], &tasks)
+        );
+
+        // as are the names of other tasks
+        assert_eq!(
+            vec![call("build", "a"), call("clean", ""), call("build", "")],
+            calls(&["build", "a", "clean", "build"], &tasks)
+        );
+        assert_eq!(
+            vec![call("build", "--target=clean")],
+            calls(&["build", "--target=clean"], &tasks)
+        );
+
+        // unknown tasks are assumed to take parameters
+        assert_eq!(vec![call("test", "all")], calls(&["test", "all"], &tasks));
+    }
+}